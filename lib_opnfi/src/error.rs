@@ -5,6 +5,10 @@ pub enum OpnFiError {
     Generic(Box<dyn error::Error + 'static>),
     IOError(io::Error),
     SerdeJsonError(serde_json::Error),
+    #[cfg(feature = "client")]
+    HttpError(reqwest::Error),
+    /// The controller answered with an unsuccessful HTTP status
+    HttpStatus(u16),
     InvalidHeader,
     InvalidInput,
     InvalidData,
@@ -48,3 +52,10 @@ impl From<serde_json::Error> for OpnFiError {
         OpnFiError::SerdeJsonError(e)
     }
 }
+
+#[cfg(feature = "client")]
+impl From<reqwest::Error> for OpnFiError {
+    fn from(e: reqwest::Error) -> Self {
+        OpnFiError::HttpError(e)
+    }
+}
@@ -0,0 +1,164 @@
+use super::payload::gateway::OpnFiDeviceState;
+
+/// Informs an adopted device may miss before it considers itself disconnected
+pub const MAX_MISSED_HEARTBEATS: u32 = 3;
+
+// ===== Events =====
+
+/// What came of an inform, drives the adoption state machine.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum OpnFiInformEvent {
+    /// The inform could not be delivered
+    Unreachable,
+    /// The controller answered with an error status, it does so until the device is adopted
+    Rejected,
+    /// The response could not be decoded, usually a key mismatch
+    InvalidResponse,
+    NoOp,
+    /// New parameters, `mgmt_cfg` carries the auth key when the device gets adopted
+    SetParam {
+        mgmt_cfg: bool,
+    },
+    /// The device was forgotten and is reset to defaults
+    SetDefault,
+    /// Any other command
+    Command,
+}
+
+// ===== Adoption State =====
+
+/// The state a device reports to the controller, moved along by the outcome of each inform.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct OpnFiAdoptionState {
+    state: OpnFiDeviceState,
+    /// Informs that could not be delivered in a row
+    missed: u32,
+}
+
+impl OpnFiAdoptionState {
+    /// Start out connected with an auth key from the controller, pending adoption without one.
+    pub fn new(adopted: bool) -> Self {
+        OpnFiAdoptionState {
+            state: if adopted {
+                OpnFiDeviceState::Connected
+            } else {
+                OpnFiDeviceState::PendingAdoption
+            },
+            missed: 0,
+        }
+    }
+
+    pub fn state(&self) -> OpnFiDeviceState {
+        self.state
+    }
+
+    /// The state to put in an inform. One that gets through reached the controller, so missed
+    /// heartbeats are reported as connected, or pending adoption without an auth key.
+    pub fn reported(&self, adopted: bool) -> OpnFiDeviceState {
+        match self.state {
+            OpnFiDeviceState::HeartbeatMissed | OpnFiDeviceState::Disconnected => {
+                Self::new(adopted).state
+            }
+            state => state,
+        }
+    }
+
+    /// Move to the state following an inform, `adopted` is whether the device held an auth key
+    /// when it sent it.
+    /// Returns the new state if it changed.
+    pub fn transition(
+        &mut self,
+        event: OpnFiInformEvent,
+        adopted: bool,
+    ) -> Option<OpnFiDeviceState> {
+        use OpnFiDeviceState::*;
+
+        if event == OpnFiInformEvent::Unreachable {
+            self.missed += 1;
+        } else {
+            self.missed = 0;
+        }
+        let next = match (event, self.state) {
+            (OpnFiInformEvent::Unreachable, Connected)
+            | (OpnFiInformEvent::Unreachable, Provisioning)
+            | (OpnFiInformEvent::Unreachable, Upgrading)
+            | (OpnFiInformEvent::Unreachable, HeartbeatMissed)
+                if self.missed < MAX_MISSED_HEARTBEATS =>
+            {
+                HeartbeatMissed
+            }
+            (OpnFiInformEvent::Unreachable, _) => Disconnected,
+            (OpnFiInformEvent::Rejected, _) if !adopted => PendingAdoption,
+            (OpnFiInformEvent::Rejected, Adopting)
+            | (OpnFiInformEvent::Rejected, AdoptionFailed) => AdoptionFailed,
+            (OpnFiInformEvent::Rejected, _) => InformError,
+            (OpnFiInformEvent::InvalidResponse, Adopting)
+            | (OpnFiInformEvent::InvalidResponse, AdoptionFailed) => AdoptionFailed,
+            (OpnFiInformEvent::InvalidResponse, _) => InformError,
+            (OpnFiInformEvent::SetParam { mgmt_cfg: true }, _) if !adopted => Adopting,
+            (OpnFiInformEvent::SetParam { .. }, _) if adopted => Provisioning,
+            (OpnFiInformEvent::SetDefault, _) => Deleting,
+            (_, _) if adopted => Connected,
+            (_, _) => PendingAdoption,
+        };
+        if next == self.state {
+            return None;
+        }
+        self.state = next;
+        Some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use OpnFiDeviceState::*;
+
+    #[test]
+    fn test_adoption() {
+        let mut adoption = OpnFiAdoptionState::new(false);
+        assert_eq!(adoption.state(), PendingAdoption);
+        assert_eq!(adoption.transition(OpnFiInformEvent::Rejected, false), None);
+        assert_eq!(
+            adoption.transition(OpnFiInformEvent::SetParam { mgmt_cfg: true }, false),
+            Some(Adopting)
+        );
+        assert_eq!(
+            adoption.transition(OpnFiInformEvent::InvalidResponse, true),
+            Some(AdoptionFailed)
+        );
+        assert_eq!(
+            adoption.transition(OpnFiInformEvent::NoOp, true),
+            Some(Connected)
+        );
+        assert_eq!(
+            adoption.transition(OpnFiInformEvent::SetParam { mgmt_cfg: false }, true),
+            Some(Provisioning)
+        );
+        assert_eq!(
+            adoption.transition(OpnFiInformEvent::SetDefault, true),
+            Some(Deleting)
+        );
+    }
+
+    #[test]
+    fn test_heartbeat_missed() {
+        let mut adoption = OpnFiAdoptionState::new(true);
+        assert_eq!(adoption.state(), Connected);
+        for _ in 1..MAX_MISSED_HEARTBEATS {
+            adoption.transition(OpnFiInformEvent::Unreachable, true);
+            assert_eq!(adoption.state(), HeartbeatMissed);
+            assert_eq!(adoption.reported(true), Connected);
+        }
+        adoption.transition(OpnFiInformEvent::Unreachable, true);
+        assert_eq!(adoption.state(), Disconnected);
+        assert_eq!(adoption.reported(true), Connected);
+        // Never adopted, nothing to miss
+        let mut adoption = OpnFiAdoptionState::new(false);
+        assert_eq!(
+            adoption.transition(OpnFiInformEvent::Unreachable, false),
+            Some(Disconnected)
+        );
+        assert_eq!(adoption.reported(false), PendingAdoption);
+    }
+}
@@ -0,0 +1,251 @@
+use super::payload::gateway::OpnFiDeviceState;
+use super::payload::OpnFiInformPayload;
+use super::protocol::{OpnFiDeviceAction, OpnFiDeviceProtocol};
+use crate::error::OpnFiError;
+use crate::Result;
+use pnet::util::MacAddr;
+use std::time::{Duration, Instant};
+
+// ===== Inform Client =====
+
+/// A blocking session with a controller, drives an [`OpnFiDeviceProtocol`] over HTTP.
+pub struct OpnFiInformClient {
+    url: String,
+    protocol: OpnFiDeviceProtocol,
+    http: reqwest::Client,
+}
+
+impl OpnFiInformClient {
+    /// Client for a device that isn't adopted yet, informs are encrypted with the master key.
+    pub fn new(url: &str, hardware_address: MacAddr) -> Self {
+        OpnFiInformClient {
+            url: url.to_string(),
+            protocol: OpnFiDeviceProtocol::new(hardware_address),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn set_url(&mut self, url: &str) {
+        self.url = url.to_string();
+    }
+
+    /// The protocol, to restore a saved session or read its state
+    pub fn protocol(&mut self) -> &mut OpnFiDeviceProtocol {
+        &mut self.protocol
+    }
+
+    pub fn state(&self) -> OpnFiDeviceState {
+        self.protocol.state()
+    }
+
+    pub fn is_adopted(&self) -> bool {
+        self.protocol.is_adopted()
+    }
+
+    /// Time to wait before the next inform
+    pub fn interval(&self) -> Duration {
+        self.protocol.interval()
+    }
+
+    /// Inform on the next `poll_inform` instead of waiting for the interval
+    pub fn inform_now(&mut self) {
+        self.protocol.inform_now();
+    }
+
+    /// Whether an inform is due at `now`, build a payload and `send` it when it is
+    pub fn poll_inform(&mut self, now: Instant) -> bool {
+        self.protocol.handle_timeout(now);
+        // `send` leaves nothing queued, so only informs can be pending here
+        let mut due = false;
+        while let Some(action) = self.protocol.poll_action() {
+            due |= action == OpnFiDeviceAction::Inform;
+        }
+        due
+    }
+
+    /// Send one payload and return what the controller asked the device to do.
+    /// Transport and decoding errors are returned after the protocol took note of them,
+    /// anything it queued for a failed inform is dropped.
+    pub fn send(&mut self, payload: OpnFiInformPayload) -> Result<Vec<OpnFiDeviceAction>> {
+        let mut actions = Vec::new();
+        let result = self.exchange(payload, &mut actions);
+        if result.is_err() {
+            while self.protocol.poll_action().is_some() {}
+        }
+        result.map(|_| actions)
+    }
+
+    fn exchange(
+        &mut self,
+        payload: OpnFiInformPayload,
+        actions: &mut Vec<OpnFiDeviceAction>,
+    ) -> Result<()> {
+        self.protocol.send_inform(payload, Instant::now())?;
+        while let Some(action) = self.protocol.poll_action() {
+            match action {
+                OpnFiDeviceAction::Transmit(data) => {
+                    let body = self.post(data)?;
+                    self.protocol.handle_response(&body)?;
+                }
+                // Informs are timed with `poll_inform`
+                OpnFiDeviceAction::Inform => {}
+                action => actions.push(action),
+            }
+        }
+        Ok(())
+    }
+
+    fn post(&mut self, data: Vec<u8>) -> Result<Vec<u8>> {
+        let mut response = match self.http.post(self.url.as_str()).body(data).send() {
+            Ok(response) => response,
+            Err(e) => {
+                self.protocol.handle_unreachable();
+                return Err(OpnFiError::from(e));
+            }
+        };
+        if !response.status().is_success() {
+            self.protocol.handle_rejected();
+            return Err(OpnFiError::HttpStatus(response.status().as_u16()));
+        }
+        let mut body = Vec::new();
+        if let Err(e) = response.copy_to(&mut body) {
+            self.protocol.handle_unreachable();
+            return Err(OpnFiError::from(e));
+        }
+        Ok(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inform::{OpnFiReadExt, OpnFiWriteExt, OpnfiInformPacket};
+    use byteorder::NetworkEndian;
+    use std::io::{self, BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::{error, thread};
+
+    type TestResult = std::result::Result<(), Box<dyn error::Error + 'static>>;
+
+    const AUTHKEY: &str = "0123456789abcdef0123456789abcdef";
+    const KEY: [u8; 16] = [
+        0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef, 0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd,
+        0xef,
+    ];
+
+    /// Answer one inform with `status` and `response`, returns the URL and the received packet.
+    fn serve(
+        status: &'static str,
+        key: Option<[u8; 16]>,
+        response: Option<OpnFiInformPayload>,
+    ) -> io::Result<(
+        String,
+        thread::JoinHandle<OpnfiInformPacket<OpnFiInformPayload>>,
+    )> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}/inform", listener.local_addr()?);
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim().to_lowercase();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some(length) = line.strip_prefix("content-length:") {
+                    content_length = length.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0u8; content_length];
+            reader.read_exact(&mut body).unwrap();
+            let request =
+                OpnfiInformPacket::read::<NetworkEndian>(key, None, &mut io::Cursor::new(body))
+                    .unwrap();
+
+            let mut data = Vec::new();
+            if let Some(payload) = response {
+                let packet = OpnfiInformPacket::new(None, 0, MacAddr::zero(), 0x3, 1, payload);
+                packet
+                    .write::<NetworkEndian>(key, [7u8; 16], &mut data)
+                    .unwrap();
+            }
+            let mut stream = reader.into_inner();
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                status,
+                data.len()
+            )
+            .unwrap();
+            stream.write_all(&data).unwrap();
+            request
+        });
+        Ok((url, handle))
+    }
+
+    #[test]
+    fn test_send() -> TestResult {
+        let noop: OpnFiInformPayload = serde_json::from_str(
+            r#"{"_type": "noop", "interval": 30, "server_time_in_utc": "1574000000"}"#,
+        )?;
+        let (url, server) = serve("200 OK", None, Some(noop))?;
+        let mac = MacAddr::new(0xfc, 0xec, 0xda, 0x00, 0x00, 0x01);
+        let mut client = OpnFiInformClient::new(&url, mac);
+        // Pending adoption from the start
+        let actions = client.send(OpnFiInformPayload::default())?;
+        assert_eq!(actions, Vec::new());
+        assert_eq!(client.state(), OpnFiDeviceState::PendingAdoption);
+        assert_eq!(client.interval(), Duration::from_secs(30));
+
+        let request = server.join().unwrap();
+        assert_eq!(request.hardware_address, mac);
+        assert_eq!(request.flags, 0x3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_send_adopted() -> TestResult {
+        let (url, server) = serve("200 OK", Some(KEY), None)?;
+        let mut client = OpnFiInformClient::new(&url, MacAddr::zero());
+        client.protocol().set_authkey(Some(AUTHKEY))?;
+        client.protocol().set_use_aes_gcm(true);
+        assert!(client.is_adopted());
+        // An empty response carries no command
+        client.send(OpnFiInformPayload::default())?;
+        assert_eq!(client.state(), OpnFiDeviceState::Connected);
+        assert_eq!(server.join().unwrap().flags, 0xb);
+        Ok(())
+    }
+
+    #[test]
+    fn test_send_rejected() -> TestResult {
+        let (url, server) = serve("404 Not Found", None, None)?;
+        let mut client = OpnFiInformClient::new(&url, MacAddr::zero());
+        match client.send(OpnFiInformPayload::default()) {
+            Err(OpnFiError::HttpStatus(404)) => {}
+            result => panic!("Unexpected result {:?}", result),
+        }
+        assert_eq!(client.state(), OpnFiDeviceState::PendingAdoption);
+        assert_eq!(client.protocol().poll_action(), None);
+        server.join().unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_poll_inform() {
+        let start = Instant::now();
+        let mut client = OpnFiInformClient::new("http://127.0.0.1:8080/inform", MacAddr::zero());
+        assert!(client.poll_inform(start));
+        assert!(!client.poll_inform(start + Duration::from_secs(1)));
+        client.inform_now();
+        assert!(client.poll_inform(start + Duration::from_secs(2)));
+        assert!(client.poll_inform(start + Duration::from_secs(2) + client.interval()));
+    }
+}
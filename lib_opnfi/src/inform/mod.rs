@@ -6,9 +6,13 @@ use byteorder::{ByteOrder, ReadBytesExt, WriteBytesExt};
 use enumflags2::BitFlags;
 use pnet::util::MacAddr;
 
+pub mod adoption;
 mod cipher;
+#[cfg(feature = "client")]
+pub mod client;
 mod compression;
 pub mod payload;
+pub mod protocol;
 
 // ===== Constants =====
 
@@ -1,3 +1,8 @@
+use super::system_cfg::OpnFiSystemConfig;
+use crate::Result;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 fn time() -> u64 {
@@ -20,6 +25,20 @@ pub enum OpnFiInformPayloadCommand {
     SetDefault(OpnFiInformPayloadSetDefaultCommand),
 }
 
+impl OpnFiInformPayloadCommand {
+    /// The `_type` of the command
+    pub fn name(&self) -> &'static str {
+        match self {
+            OpnFiInformPayloadCommand::NoOp(_) => "noop",
+            OpnFiInformPayloadCommand::SetParam(_) => "setparam",
+            OpnFiInformPayloadCommand::Upgrade(_) => "upgrade",
+            OpnFiInformPayloadCommand::Reboot(_) => "reboot",
+            OpnFiInformPayloadCommand::Cmd(_) => "cmd",
+            OpnFiInformPayloadCommand::SetDefault(_) => "setdefault",
+        }
+    }
+}
+
 // ===== NoOp =====
 
 /// NoOp command with next inform interval
@@ -64,6 +83,24 @@ impl OpnFiInformPayloadSetParamsCommand {
     pub fn is_system_cfg(&self) -> bool {
         self.system_cfg.is_some()
     }
+
+    /// Parse the gateway system config if one was sent
+    pub fn parse_system_cfg(&self) -> Option<Result<OpnFiSystemConfig>> {
+        self.system_cfg
+            .as_ref()
+            .map(|cfg| OpnFiSystemConfig::from_json(cfg))
+    }
+}
+
+/// `key=value` pairs of a `mgmt_cfg`, lines without a value are skipped
+pub fn parse_mgmt_cfg(mgmt_cfg: &str) -> Vec<(&str, &str)> {
+    mgmt_cfg
+        .lines()
+        .filter_map(|line| {
+            let mut pair = line.splitn(2, '=');
+            Some((pair.next()?, pair.next()?))
+        })
+        .collect()
 }
 
 impl Default for OpnFiInformPayloadSetParamsCommand {
@@ -113,6 +150,32 @@ pub struct OpnFiInformPayloadRebootCommand {
     time: u64,
 }
 
+impl OpnFiInformPayloadRebootCommand {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn datetime(&self) -> &str {
+        &self.datetime
+    }
+
+    pub fn device_id(&self) -> &str {
+        &self.device_id
+    }
+
+    /// Anything other than a hard reboot is treated as soft
+    pub fn reboot_type(&self) -> OpnFiRebootType {
+        match self.reboot_type.as_str() {
+            "hard" => OpnFiRebootType::Hard,
+            _ => OpnFiRebootType::Soft,
+        }
+    }
+
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+}
+
 impl Default for OpnFiInformPayloadRebootCommand {
     fn default() -> Self {
         let time = time();
@@ -127,6 +190,13 @@ impl Default for OpnFiInformPayloadRebootCommand {
     }
 }
 
+/// How the device should be rebooted
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum OpnFiRebootType {
+    Soft,
+    Hard,
+}
+
 // ===== CMD =====
 
 /// Generic command
@@ -140,6 +210,42 @@ pub struct OpnFiInformPayloadCmdCommand {
     server_time_in_utc: String,
     pub time: u64,
     pub use_alert: bool,
+    /// Command specific parameters
+    #[serde(flatten)]
+    pub params: BTreeMap<String, Value>,
+}
+
+impl OpnFiInformPayloadCmdCommand {
+    /// Parse the free-form `cmd` and its parameters
+    pub fn command(&self) -> OpnFiInformCmd {
+        let string_param = |name: &str| {
+            self.params
+                .get(name)
+                .and_then(|v| v.as_str())
+                .map(|v| v.to_string())
+        };
+        let mac = string_param("mac");
+        match (self.cmd.as_str(), mac) {
+            ("speed-test", _) => OpnFiInformCmd::SpeedTest,
+            ("speed-test-status", _) => OpnFiInformCmd::SpeedTestStatus,
+            ("set-locate", _) => OpnFiInformCmd::SetLocate,
+            ("unset-locate", _) => OpnFiInformCmd::UnsetLocate,
+            ("restart-provision", _) => OpnFiInformCmd::RestartProvision,
+            ("kick-sta", Some(mac)) => OpnFiInformCmd::KickSta { mac },
+            ("block-sta", Some(mac)) => OpnFiInformCmd::BlockSta { mac },
+            ("unblock-sta", Some(mac)) => OpnFiInformCmd::UnblockSta { mac },
+            ("power-cycle", _) => match self
+                .params
+                .get("port_idx")
+                .and_then(|v| v.as_u64())
+                .and_then(|v| u32::try_from(v).ok())
+            {
+                Some(port_idx) => OpnFiInformCmd::PowerCycle { port_idx },
+                None => OpnFiInformCmd::Unknown(self.cmd.clone()),
+            },
+            (cmd, _) => OpnFiInformCmd::Unknown(cmd.to_string()),
+        }
+    }
 }
 
 impl Default for OpnFiInformPayloadCmdCommand {
@@ -152,6 +258,40 @@ impl Default for OpnFiInformPayloadCmdCommand {
             server_time_in_utc: time().to_string(),
             time: time(),
             use_alert: true,
+            params: BTreeMap::new(),
+        }
+    }
+}
+
+/// Known `cmd` commands, commands missing a required parameter are `Unknown`
+#[derive(PartialEq, Clone, Debug)]
+pub enum OpnFiInformCmd {
+    SpeedTest,
+    SpeedTestStatus,
+    SetLocate,
+    UnsetLocate,
+    RestartProvision,
+    KickSta { mac: String },
+    BlockSta { mac: String },
+    UnblockSta { mac: String },
+    PowerCycle { port_idx: u32 },
+    Unknown(String),
+}
+
+impl OpnFiInformCmd {
+    /// The `cmd` string of the command
+    pub fn name(&self) -> &str {
+        match self {
+            OpnFiInformCmd::SpeedTest => "speed-test",
+            OpnFiInformCmd::SpeedTestStatus => "speed-test-status",
+            OpnFiInformCmd::SetLocate => "set-locate",
+            OpnFiInformCmd::UnsetLocate => "unset-locate",
+            OpnFiInformCmd::RestartProvision => "restart-provision",
+            OpnFiInformCmd::KickSta { .. } => "kick-sta",
+            OpnFiInformCmd::BlockSta { .. } => "block-sta",
+            OpnFiInformCmd::UnblockSta { .. } => "unblock-sta",
+            OpnFiInformCmd::PowerCycle { .. } => "power-cycle",
+            OpnFiInformCmd::Unknown(cmd) => cmd.as_str(),
         }
     }
 }
@@ -171,3 +311,104 @@ impl Default for OpnFiInformPayloadSetDefaultCommand {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json;
+    use std::error;
+
+    type TestResult = std::result::Result<(), Box<dyn error::Error + 'static>>;
+
+    #[test]
+    fn test_parse_mgmt_cfg() {
+        assert_eq!(
+            parse_mgmt_cfg("authkey=0123\nbroken\nmgmt_url=https://unifi:8443/manage?a=b\n"),
+            vec![
+                ("authkey", "0123"),
+                ("mgmt_url", "https://unifi:8443/manage?a=b")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reboot_command() -> TestResult {
+        let cmd: OpnFiInformPayloadCommand = serde_json::from_str(
+            r#"{
+                "_type": "reboot",
+                "_id": "5dd2a8e0e4b0f0e1a1b2c3d4",
+                "datetime": "2019-11-18T14:26:08Z",
+                "device_id": "5dd2a7c0e4b0f0e1a1b2c3d0",
+                "reboot_type": "hard",
+                "server_time_in_utc": "1574087168000",
+                "time": 1574087168
+            }"#,
+        )?;
+        match cmd {
+            OpnFiInformPayloadCommand::Reboot(reboot) => {
+                assert_eq!(reboot.id(), "5dd2a8e0e4b0f0e1a1b2c3d4");
+                assert_eq!(reboot.device_id(), "5dd2a7c0e4b0f0e1a1b2c3d0");
+                assert_eq!(reboot.reboot_type(), OpnFiRebootType::Hard);
+                assert_eq!(reboot.time(), 1574087168);
+            }
+            cmd => panic!("Unexpected command {:?}", cmd),
+        }
+        assert_eq!(
+            OpnFiInformPayloadRebootCommand::default().reboot_type(),
+            OpnFiRebootType::Soft
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_cmd_command() -> TestResult {
+        let cmd: OpnFiInformPayloadCommand = serde_json::from_str(
+            r#"{
+                "_type": "cmd",
+                "_id": "5dd2a8e0e4b0f0e1a1b2c3d4",
+                "cmd": "kick-sta",
+                "date_time": "2019-11-18T14:26:08Z",
+                "device_id": "5dd2a7c0e4b0f0e1a1b2c3d0",
+                "mac": "00:de:ad:be:ef:00",
+                "server_time_in_utc": "1574087168000",
+                "time": 1574087168,
+                "use_alert": false
+            }"#,
+        )?;
+        match cmd {
+            OpnFiInformPayloadCommand::Cmd(cmd) => assert_eq!(
+                cmd.command(),
+                OpnFiInformCmd::KickSta {
+                    mac: "00:de:ad:be:ef:00".to_string()
+                }
+            ),
+            cmd => panic!("Unexpected command {:?}", cmd),
+        }
+
+        let mut cmd = OpnFiInformPayloadCmdCommand {
+            cmd: "set-locate".to_string(),
+            ..OpnFiInformPayloadCmdCommand::default()
+        };
+        assert_eq!(cmd.command(), OpnFiInformCmd::SetLocate);
+        cmd.cmd = "kick-sta".to_string();
+        assert_eq!(
+            cmd.command(),
+            OpnFiInformCmd::Unknown("kick-sta".to_string())
+        );
+        cmd.cmd = "self-destruct".to_string();
+        assert_eq!(cmd.command().name(), "self-destruct");
+
+        // A port index past u32 is not truncated onto another port
+        cmd.cmd = "power-cycle".to_string();
+        cmd.params
+            .insert("port_idx".to_string(), serde_json::json!(3));
+        assert_eq!(cmd.command(), OpnFiInformCmd::PowerCycle { port_idx: 3 });
+        cmd.params
+            .insert("port_idx".to_string(), serde_json::json!(1u64 << 32 | 3));
+        assert_eq!(
+            cmd.command(),
+            OpnFiInformCmd::Unknown("power-cycle".to_string())
+        );
+        Ok(())
+    }
+}
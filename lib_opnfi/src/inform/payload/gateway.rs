@@ -1,11 +1,22 @@
 use super::net::*;
 use super::stats::*;
+use enumflags2::BitFlags;
+use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
 
 // ===== Gatway Inform =====
 
 /// Gateway inform payload, I may be missing a few fields.
 #[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
 pub struct OpnFiInformGatewayPayload {
+    /// Mainboard model of the host, from DMI
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub board_name: Option<String>,
+    /// Mainboard manufacturer of the host, from DMI
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub board_vendor: Option<String>,
     pub bootrom_version: String,
     pub cfgversion: String,
     #[serde(default)]
@@ -16,7 +27,17 @@ pub struct OpnFiInformGatewayPayload {
     pub config_port_table: Vec<OpnFiInformConfigPortTableItem>,
     pub default: bool,
     pub discovery_response: bool,
-    pub fw_caps: i32,
+    #[serde(rename = "dpi-stats")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    pub dpi_stats: Vec<OpnFiInformDpiClientStats>,
+    #[serde(rename = "dpi-stats-table")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub dpi_stats_table: Option<OpnFiInformDpiStatsTable>,
+    /// Unknown bits are dropped when decoded
+    #[serde(with = "caps")]
+    pub fw_caps: BitFlags<OpnFiFirewallCap>,
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(default)]
     pub guest_token: Option<String>,
@@ -38,15 +59,223 @@ pub struct OpnFiInformGatewayPayload {
     pub model: String,
     pub model_display: String,
     pub netmask: String,
-    pub radius_caps: i32,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    pub network_table: Vec<OpnFiInformNetworkTableEntry>,
+    /// Release of the host OS, not read by the controller
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub os_version: Option<String>,
+    /// Unknown bits are dropped when decoded
+    #[serde(with = "caps")]
+    pub radius_caps: BitFlags<OpnFiRadiusCap>,
     pub required_version: String,
     pub selfrun_beacon: bool,
     pub serial: String,
-    pub state: i32,
+    #[serde(rename = "speedtest-status")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub speedtest_status: Option<OpnFiInformSpeedTestStatus>,
+    pub state: OpnFiDeviceState,
+    #[serde(default)]
+    pub sys_stats: OpnFiInformSysStats,
     #[serde(rename = "system-stats")]
     pub system_status: OpnFiInformSystemStatus,
     pub time: usize,
     pub uplink: String,
     pub uptime: usize,
     pub version: String,
+    /// Not read by the controller, for tools reading informs
+    #[serde(rename = "wan-mode")]
+    #[serde(default)]
+    pub wan_mode: OpnFiInformWanMode,
+    /// Not read by the controller, for tools reading informs
+    #[serde(rename = "wan-status")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    pub wan_status: Vec<OpnFiInformWanStatus>,
+}
+
+// ===== Device State =====
+
+/// State of a device as the controller tracks it, sent as its numeric code.
+#[derive(PartialEq, Copy, Clone, Debug, Default)]
+pub enum OpnFiDeviceState {
+    #[default]
+    Disconnected,
+    Connected,
+    PendingAdoption,
+    FirmwareMismatch,
+    Upgrading,
+    Provisioning,
+    HeartbeatMissed,
+    Adopting,
+    Deleting,
+    InformError,
+    AdoptionFailed,
+    Isolated,
+    /// A code added by a newer controller
+    Unknown(i32),
+}
+
+impl OpnFiDeviceState {
+    pub fn code(self) -> i32 {
+        match self {
+            OpnFiDeviceState::Disconnected => 0,
+            OpnFiDeviceState::Connected => 1,
+            OpnFiDeviceState::PendingAdoption => 2,
+            OpnFiDeviceState::FirmwareMismatch => 3,
+            OpnFiDeviceState::Upgrading => 4,
+            OpnFiDeviceState::Provisioning => 5,
+            OpnFiDeviceState::HeartbeatMissed => 6,
+            OpnFiDeviceState::Adopting => 7,
+            OpnFiDeviceState::Deleting => 8,
+            OpnFiDeviceState::InformError => 9,
+            OpnFiDeviceState::AdoptionFailed => 10,
+            OpnFiDeviceState::Isolated => 11,
+            OpnFiDeviceState::Unknown(code) => code,
+        }
+    }
+
+    pub fn from_code(code: i32) -> Self {
+        match code {
+            0 => OpnFiDeviceState::Disconnected,
+            1 => OpnFiDeviceState::Connected,
+            2 => OpnFiDeviceState::PendingAdoption,
+            3 => OpnFiDeviceState::FirmwareMismatch,
+            4 => OpnFiDeviceState::Upgrading,
+            5 => OpnFiDeviceState::Provisioning,
+            6 => OpnFiDeviceState::HeartbeatMissed,
+            7 => OpnFiDeviceState::Adopting,
+            8 => OpnFiDeviceState::Deleting,
+            9 => OpnFiDeviceState::InformError,
+            10 => OpnFiDeviceState::AdoptionFailed,
+            11 => OpnFiDeviceState::Isolated,
+            code => OpnFiDeviceState::Unknown(code),
+        }
+    }
+}
+
+impl Serialize for OpnFiDeviceState {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i32(self.code())
+    }
+}
+
+impl<'de> Deserialize<'de> for OpnFiDeviceState {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Self::from_code(i32::deserialize(deserializer)?))
+    }
+}
+
+// ===== Capabilities =====
+
+/// Firewall features a gateway advertises in `fw_caps`.
+/// The controller documents none of these bits, their values are unverified guesses that no
+/// captured inform in this repository confirms. Features with a `has_*` field are also
+/// reported there.
+#[derive(BitFlags, Copy, Clone, Debug, PartialEq)]
+#[repr(u32)]
+pub enum OpnFiFirewallCap {
+    /// Also `has_dpi`
+    Dpi = 0x001,
+    /// Also `has_dnsmasq_hostfile_update`
+    DnsmasqHostfileUpdate = 0x002,
+    /// Also `has_default_route_distance`
+    DefaultRouteDistance = 0x004,
+    /// Also `has_ssh_disable`
+    SshDisable = 0x008,
+    /// Also `has_vti`
+    Vti = 0x010,
+    SpeedTest = 0x040,
+    Ipv6 = 0x080,
+    LoadBalance = 0x100,
+    Offload = 0x200,
+    /// Also `has_porta`
+    Porta = 0x400,
+}
+
+/// RADIUS features a gateway advertises in `radius_caps`, unverified like [`OpnFiFirewallCap`].
+#[derive(BitFlags, Copy, Clone, Debug, PartialEq)]
+#[repr(u32)]
+pub enum OpnFiRadiusCap {
+    Server = 0x1,
+    Accounting = 0x2,
+}
+
+/// Capabilities as a plain integer.
+/// Decoding is lossy: bits without a variant are dropped, so a decoded payload encodes to a
+/// smaller value than it was read from.
+mod caps {
+    use enumflags2::{BitFlags, RawBitFlags};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<T, S>(caps: &BitFlags<T>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: RawBitFlags<Type = u32>,
+        S: Serializer,
+    {
+        serializer.serialize_u32(caps.bits())
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<BitFlags<T>, D::Error>
+    where
+        T: RawBitFlags<Type = u32>,
+        D: Deserializer<'de>,
+    {
+        Ok(BitFlags::from_bits_truncate(u32::deserialize(
+            deserializer,
+        )?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error;
+
+    type TestResult = std::result::Result<(), Box<dyn error::Error + 'static>>;
+
+    #[test]
+    fn test_caps() -> TestResult {
+        let payload = OpnFiInformGatewayPayload {
+            fw_caps: OpnFiFirewallCap::Dpi | OpnFiFirewallCap::SpeedTest,
+            radius_caps: BitFlags::empty(),
+            ..OpnFiInformGatewayPayload::default()
+        };
+        let mut json = serde_json::to_value(&payload)?;
+        assert_eq!(json["fw_caps"], 0x41);
+        assert_eq!(json["radius_caps"], 0);
+
+        // Bits we don't know about are dropped
+        json["fw_caps"] = serde_json::json!(0x7fff_ffff);
+        json["radius_caps"] = serde_json::json!(0x7);
+        let payload: OpnFiInformGatewayPayload = serde_json::from_value(json)?;
+        assert!(payload.fw_caps.is_all());
+        assert_eq!(payload.radius_caps.bits(), 0x3);
+        let json = serde_json::to_value(&payload)?;
+        assert_eq!(json["fw_caps"], BitFlags::<OpnFiFirewallCap>::all().bits());
+        assert_eq!(json["radius_caps"], 0x3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_device_state() -> TestResult {
+        let payload = OpnFiInformGatewayPayload {
+            state: OpnFiDeviceState::PendingAdoption,
+            ..OpnFiInformGatewayPayload::default()
+        };
+        let mut json = serde_json::to_value(&payload)?;
+        assert_eq!(json["state"], 2);
+
+        json["state"] = serde_json::json!(10);
+        let payload: OpnFiInformGatewayPayload = serde_json::from_value(json.clone())?;
+        assert_eq!(payload.state, OpnFiDeviceState::AdoptionFailed);
+        // Codes from a newer controller are kept as they are
+        json["state"] = serde_json::json!(42);
+        let payload: OpnFiInformGatewayPayload = serde_json::from_value(json)?;
+        assert_eq!(payload.state, OpnFiDeviceState::Unknown(42));
+        assert_eq!(serde_json::to_value(&payload)?["state"], 42);
+        Ok(())
+    }
 }
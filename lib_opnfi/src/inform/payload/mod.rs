@@ -12,6 +12,7 @@ pub mod command;
 pub mod gateway;
 pub mod net;
 pub mod stats;
+pub mod system_cfg;
 
 /// Main OpnFi inform payload enum.
 #[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
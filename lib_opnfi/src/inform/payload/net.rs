@@ -7,6 +7,7 @@ pub enum OpnFiInformNetworkConfig {
     Disabled,
     DHCP,
     Static(OpnFiInformNetworkConfigStatic),
+    PPPoE(OpnFiInformNetworkConfigPPPoE),
 }
 
 impl Default for OpnFiInformNetworkConfig {
@@ -28,6 +29,12 @@ pub struct OpnFiInformNetworkConfigStatic {
     pub dns2: Option<String>,
 }
 
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct OpnFiInformNetworkConfigPPPoE {
+    #[serde(default)]
+    pub username: String,
+}
+
 // ===== Config Port Table =====
 
 /// Port config, not sure how this is used, seems to be ignored or I'm missing a step.
@@ -45,21 +52,63 @@ impl OpnFiInformConfigPortTableItem {
 
 // ===== Interface =====
 
-/// Network interface for inform
+/// What backs an interface
+#[derive(Serialize, Deserialize, PartialEq, Copy, Clone, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum OpnFiInformInterfaceType {
+    Ethernet,
+    Vlan,
+    Bridge,
+    Bond,
+    Virtual,
+}
+
+impl Default for OpnFiInformInterfaceType {
+    fn default() -> Self {
+        OpnFiInformInterfaceType::Ethernet
+    }
+}
+
+/// Network interface for inform, unconfigured interfaces have an empty ip and netmask.
 #[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
 pub struct OpnFiInformNetworkInterface {
+    /// Every IPv4 and IPv6 address in CIDR notation
+    #[serde(default)]
+    pub addresses: Vec<String>,
+    /// Percentage of WAN monitor probes answered
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub availability: Option<f64>,
+    /// Lost WAN monitor probes
     pub drops: usize,
     pub enabled: bool,
     pub full_duplex: bool,
     pub gateways: Vec<String>,
     pub ip: String,
+    /// IPv6 prefixes delegated to the interface through DHCPv6-PD
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    pub ipv6_pd: Vec<String>,
+    /// WAN round trip in milliseconds
     pub latency: usize,
     pub mac: String,
+    /// Bridge members or bond slaves
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    pub members: Vec<String>,
     pub name: String,
     pub nameservers: Vec<String>,
     pub netmask: String,
     pub num_port: usize,
+    /// Interface a VLAN is tagged on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub parent: Option<String>,
     pub rx_bytes: usize,
+    /// Received bytes per second since the previous inform
+    #[serde(rename = "rx_bytes-r")]
+    #[serde(default)]
+    pub rx_bytes_r: usize,
     pub rx_dropped: usize,
     pub rx_errors: usize,
     pub rx_multicast: usize,
@@ -69,11 +118,119 @@ pub struct OpnFiInformNetworkInterface {
     pub speedtest_ping: usize,
     pub speedtest_status: String,
     pub tx_bytes: usize,
+    /// Transmitted bytes per second since the previous inform
+    #[serde(rename = "tx_bytes-r")]
+    #[serde(default)]
+    pub tx_bytes_r: usize,
     pub tx_dropped: usize,
     pub tx_errors: usize,
     pub tx_packets: usize,
+    #[serde(rename = "type")]
+    #[serde(default)]
+    pub if_type: OpnFiInformInterfaceType,
     pub up: bool,
     pub uptime: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub vlan: Option<u16>,
+    /// Throughput in Mbit/s
     pub xput_down: usize,
     pub xput_up: usize,
 }
+
+// ===== Network Table =====
+
+/// A client seen on a network
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct OpnFiInformHost {
+    /// Seconds since the client was last seen
+    pub age: usize,
+    pub authorized: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub hostname: Option<String>,
+    pub ip: String,
+    pub mac: String,
+    /// Open connections through the gateway
+    #[serde(default)]
+    pub num_connections: usize,
+    #[serde(default)]
+    pub rx_bytes: usize,
+    #[serde(default)]
+    pub rx_packets: usize,
+    #[serde(default)]
+    pub tx_bytes: usize,
+    #[serde(default)]
+    pub tx_packets: usize,
+}
+
+/// A LAN network and the clients on it
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct OpnFiInformNetworkTableEntry {
+    /// Primary address in CIDR notation
+    pub address: String,
+    #[serde(default)]
+    pub addresses: Vec<String>,
+    #[serde(default)]
+    pub host_table: Vec<OpnFiInformHost>,
+    pub mac: String,
+    pub name: String,
+    pub num_sta: usize,
+    pub up: bool,
+}
+
+// ===== WAN Status =====
+
+/// How traffic is spread over multiple WANs
+#[derive(Serialize, Deserialize, PartialEq, Copy, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum OpnFiInformWanMode {
+    Failover,
+    LoadBalance,
+}
+
+impl Default for OpnFiInformWanMode {
+    fn default() -> Self {
+        OpnFiInformWanMode::Failover
+    }
+}
+
+/// Health check result and failover state of a WAN
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct OpnFiInformWanStatus {
+    /// Port name, e.g. WAN or WAN2
+    pub name: String,
+    pub ifname: String,
+    #[serde(default)]
+    pub gateway: String,
+    /// Passed the last health check
+    pub up: bool,
+    /// Carrying traffic, only one WAN is active in failover mode
+    pub active: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error;
+
+    type TestResult = std::result::Result<(), Box<dyn error::Error + 'static>>;
+
+    #[test]
+    fn test_network_config() -> TestResult {
+        let pppoe = OpnFiInformNetworkConfig::PPPoE(OpnFiInformNetworkConfigPPPoE {
+            username: "opnfi@isp.example".to_string(),
+        });
+        let json = serde_json::to_string(&pppoe)?;
+        assert_eq!(json, r#"{"type":"pppoe","username":"opnfi@isp.example"}"#);
+        assert_eq!(
+            serde_json::from_str::<OpnFiInformNetworkConfig>(&json)?,
+            pppoe
+        );
+        assert_eq!(
+            serde_json::to_string(&OpnFiInformNetworkConfig::DHCP)?,
+            r#"{"type":"dhcp"}"#
+        );
+        Ok(())
+    }
+}
@@ -1,20 +1,309 @@
+use std::collections::BTreeMap;
+
 // ===== System Status =====
 
-/// System status
-#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+/// System status, the controller expects every number as a string.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
 pub struct OpnFiInformSystemStatus {
-    pub cpu: String,
-    pub mem: String,
+    /// CPU usage in percent
+    #[serde(with = "string_number")]
+    pub cpu: f64,
+    /// Usage of each core in percent
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(with = "string_number::vec")]
+    #[serde(default)]
+    pub cpu_cores: Vec<f64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    pub disks: Vec<OpnFiInformDiskUsage>,
+    /// Fan speeds in RPM by sensor
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    #[serde(with = "string_number::map")]
+    #[serde(default)]
+    pub fans: BTreeMap<String, u64>,
+    /// Memory usage in percent
+    #[serde(with = "string_number")]
+    pub mem: f64,
+    /// Temperatures in degrees Celsius by sensor
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    #[serde(with = "string_number::map")]
+    #[serde(default)]
+    pub temps: BTreeMap<String, f64>,
+}
+
+/// Load and memory in absolute numbers, reported as `sys_stats` next to `system-stats`
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct OpnFiInformSysStats {
+    #[serde(with = "string_number")]
+    #[serde(default)]
+    pub loadavg_1: f64,
+    #[serde(with = "string_number")]
+    #[serde(default)]
+    pub loadavg_15: f64,
+    #[serde(with = "string_number")]
+    #[serde(default)]
+    pub loadavg_5: f64,
+    /// Buffers and page cache in bytes
+    #[serde(with = "string_number")]
+    #[serde(default)]
+    pub mem_buffer: u64,
+    #[serde(with = "string_number")]
+    #[serde(default)]
+    pub mem_total: u64,
+    #[serde(with = "string_number")]
+    #[serde(default)]
+    pub mem_used: u64,
+}
+
+/// Space used on a mounted filesystem
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct OpnFiInformDiskUsage {
+    pub mount: String,
+    /// Size in bytes
+    #[serde(with = "string_number")]
+    pub total: u64,
+    /// Used bytes
+    #[serde(with = "string_number")]
+    pub used: u64,
+}
+
+// ===== Speed Test Status =====
+
+/// WAN speed test progress and results, reported as `speedtest-status`
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct OpnFiInformSpeedTestStatus {
+    /// Latency in milliseconds
+    pub latency: usize,
+    /// Unix time the last test was started
+    pub rundate: usize,
+    /// Seconds the last test took
+    pub runtime: usize,
+    pub status_download: usize,
+    pub status_ping: usize,
+    pub status_summary: usize,
+    pub status_upload: usize,
+    /// Download throughput in Mbps
+    pub xput_download: f64,
+    /// Upload throughput in Mbps
+    pub xput_upload: f64,
+}
+
+impl OpnFiInformSpeedTestStatus {
+    pub const STATUS_IDLE: usize = 0;
+    pub const STATUS_RUNNING: usize = 1;
+    pub const STATUS_DONE: usize = 2;
+    /// The test stopped on an error, its results are not valid
+    pub const STATUS_FAILED: usize = 3;
+}
+
+// ===== DPI Stats =====
+
+/// Traffic of one application, `cat` is the controller's DPI category and `app` an id within it.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct OpnFiInformDpiStat {
+    pub app: u32,
+    pub cat: u32,
+    pub rx_bytes: usize,
+    pub rx_packets: usize,
+    pub tx_bytes: usize,
+    pub tx_packets: usize,
 }
 
-impl OpnFiInformSystemStatus {
-    pub fn new(cpu: String, mem: String) -> Self {
-        OpnFiInformSystemStatus { cpu, mem }
+/// Application traffic of one client, reported in `dpi-stats`
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct OpnFiInformDpiClientStats {
+    pub mac: String,
+    #[serde(default)]
+    pub stats: Vec<OpnFiInformDpiStat>,
+}
+
+/// Traffic of one category over all clients
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct OpnFiInformDpiCategoryStat {
+    pub cat: u32,
+    #[serde(default)]
+    pub apps: Vec<u32>,
+    pub rx_bytes: usize,
+    pub rx_packets: usize,
+    pub tx_bytes: usize,
+    pub tx_packets: usize,
+}
+
+/// Gateway wide DPI totals, reported in `dpi-stats-table`
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct OpnFiInformDpiStatsTable {
+    #[serde(default)]
+    pub by_app: Vec<OpnFiInformDpiStat>,
+    #[serde(default)]
+    pub by_cat: Vec<OpnFiInformDpiCategoryStat>,
+}
+
+// ===== String Numbers =====
+
+/// Serde helpers writing numbers as strings, reading accepts either form.
+mod string_number {
+    use serde::de::{self, Deserialize, Deserializer, Visitor};
+    use serde::ser::{Serialize, Serializer};
+    use std::collections::BTreeMap;
+    use std::fmt::{self, Display};
+    use std::marker::PhantomData;
+    use std::str::FromStr;
+
+    pub fn serialize<T: Display, S: Serializer>(
+        value: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(StringNumberVisitor(PhantomData))
+    }
+
+    struct StringNumberVisitor<T>(PhantomData<T>);
+
+    impl<'de, T> Visitor<'de> for StringNumberVisitor<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        type Value = T;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a number or a string holding one")
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<T, E> {
+            T::from_str(value.trim()).map_err(E::custom)
+        }
+
+        fn visit_u64<E: de::Error>(self, value: u64) -> Result<T, E> {
+            self.visit_str(&value.to_string())
+        }
+
+        fn visit_i64<E: de::Error>(self, value: i64) -> Result<T, E> {
+            self.visit_str(&value.to_string())
+        }
+
+        fn visit_f64<E: de::Error>(self, value: f64) -> Result<T, E> {
+            self.visit_str(&value.to_string())
+        }
+    }
+
+    /// A single number, used for the elements of collections
+    struct StringNumber<T>(T);
+
+    impl<T: Display> Serialize for StringNumber<T> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serialize(&self.0, serializer)
+        }
+    }
+
+    impl<'de, T> Deserialize<'de> for StringNumber<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserialize(deserializer).map(StringNumber)
+        }
+    }
+
+    pub mod vec {
+        use super::*;
+
+        pub fn serialize<T: Display, S: Serializer>(
+            values: &[T],
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            serializer.collect_seq(values.iter().map(StringNumber))
+        }
+
+        pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Vec<T>, D::Error>
+        where
+            T: FromStr,
+            T::Err: Display,
+            D: Deserializer<'de>,
+        {
+            let values: Vec<StringNumber<T>> = Vec::deserialize(deserializer)?;
+            Ok(values.into_iter().map(|value| value.0).collect())
+        }
+    }
+
+    pub mod map {
+        use super::*;
+
+        pub fn serialize<T: Display, S: Serializer>(
+            values: &BTreeMap<String, T>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            serializer.collect_map(values.iter().map(|(key, value)| (key, StringNumber(value))))
+        }
+
+        pub fn deserialize<'de, T, D>(deserializer: D) -> Result<BTreeMap<String, T>, D::Error>
+        where
+            T: FromStr,
+            T::Err: Display,
+            D: Deserializer<'de>,
+        {
+            let values: BTreeMap<String, StringNumber<T>> = BTreeMap::deserialize(deserializer)?;
+            Ok(values
+                .into_iter()
+                .map(|(key, value)| (key, value.0))
+                .collect())
+        }
     }
 }
 
-impl Default for OpnFiInformSystemStatus {
-    fn default() -> Self {
-        Self::new("0".to_string(), "0".to_string())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error;
+
+    type TestResult = std::result::Result<(), Box<dyn error::Error + 'static>>;
+
+    #[test]
+    fn test_system_status() -> TestResult {
+        let mut status = OpnFiInformSystemStatus {
+            cpu: 12.5,
+            cpu_cores: vec![20.0, 5.0],
+            mem: 40.0,
+            ..OpnFiInformSystemStatus::default()
+        };
+        status.temps.insert("CPU".to_string(), 51.5);
+        let json = serde_json::to_value(&status)?;
+        assert_eq!(json["cpu"], "12.5");
+        assert_eq!(json["cpu_cores"], serde_json::json!(["20", "5"]));
+        assert_eq!(json["temps"]["CPU"], "51.5");
+        assert!(json.get("fans").is_none());
+        assert_eq!(
+            serde_json::from_value::<OpnFiInformSystemStatus>(json)?,
+            status
+        );
+
+        // Older payloads only have cpu and mem, possibly as plain numbers
+        let status: OpnFiInformSystemStatus = serde_json::from_str(r#"{"cpu":"3","mem":17}"#)?;
+        assert_eq!(status.cpu, 3.0);
+        assert_eq!(status.mem, 17.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sys_stats() -> TestResult {
+        let stats = OpnFiInformSysStats {
+            loadavg_1: 0.08,
+            mem_total: 4096,
+            ..OpnFiInformSysStats::default()
+        };
+        let json = serde_json::to_value(&stats)?;
+        assert_eq!(json["loadavg_1"], "0.08");
+        assert_eq!(json["mem_total"], "4096");
+        assert_eq!(serde_json::from_value::<OpnFiInformSysStats>(json)?, stats);
+        Ok(())
     }
 }
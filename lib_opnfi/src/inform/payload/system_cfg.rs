@@ -0,0 +1,271 @@
+use crate::{error::OpnFiError, Result};
+use serde_json;
+use std::collections::BTreeMap;
+
+// ===== System Config =====
+
+/// Gateway system config sent by the controller in `system_cfg`.
+/// This is a JSON copy of the EdgeOS config tree, only the parts OpnFi uses are modeled.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+#[serde(default)]
+pub struct OpnFiSystemConfig {
+    pub interfaces: OpnFiSystemConfigInterfaces,
+    pub service: OpnFiSystemConfigService,
+    pub system: OpnFiSystemConfigSystem,
+}
+
+impl OpnFiSystemConfig {
+    pub fn from_json(data: &str) -> Result<Self> {
+        serde_json::from_str(data).map_err(OpnFiError::from)
+    }
+
+    /// Flatten the DHCP server shared networks into a list of LAN networks.
+    pub fn lan_networks(&self) -> Vec<OpnFiLanNetwork> {
+        let mut networks = Vec::new();
+        for (name, shared) in &self.service.dhcp_server.shared_network_name {
+            for (subnet, cfg) in &shared.subnet {
+                let ranges = cfg
+                    .start
+                    .iter()
+                    .map(|(start, range)| (start.clone(), range.stop.clone()))
+                    .collect();
+                let static_leases = cfg
+                    .static_mapping
+                    .iter()
+                    .map(|(name, mapping)| OpnFiStaticLease {
+                        name: name.clone(),
+                        mac: mapping.mac_address.clone(),
+                        ip: mapping.ip_address.clone(),
+                    })
+                    .collect();
+                networks.push(OpnFiLanNetwork {
+                    name: name.clone(),
+                    enabled: shared.disable.is_none(),
+                    subnet: subnet.clone(),
+                    router: cfg.default_router.clone(),
+                    dns_servers: cfg.dns_server.clone(),
+                    domain_name: cfg
+                        .domain_name
+                        .clone()
+                        .or_else(|| self.system.domain_name.clone()),
+                    lease_time: cfg.lease.as_ref().and_then(|l| l.parse().ok()),
+                    ranges,
+                    static_leases,
+                });
+            }
+        }
+        networks
+    }
+}
+
+// ===== Interfaces =====
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+#[serde(default)]
+pub struct OpnFiSystemConfigInterfaces {
+    pub ethernet: BTreeMap<String, OpnFiSystemConfigEthernet>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+#[serde(default)]
+pub struct OpnFiSystemConfigEthernet {
+    pub address: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub vif: BTreeMap<String, OpnFiSystemConfigEthernet>,
+}
+
+// ===== Services =====
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct OpnFiSystemConfigService {
+    pub dhcp_server: OpnFiSystemConfigDhcpServer,
+    pub dns: OpnFiSystemConfigDns,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct OpnFiSystemConfigDhcpServer {
+    pub shared_network_name: BTreeMap<String, OpnFiSystemConfigSharedNetwork>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct OpnFiSystemConfigSharedNetwork {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disable: Option<String>,
+    pub subnet: BTreeMap<String, OpnFiSystemConfigSubnet>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct OpnFiSystemConfigSubnet {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_router: Option<String>,
+    pub dns_server: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub domain_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lease: Option<String>,
+    pub start: BTreeMap<String, OpnFiSystemConfigDhcpRange>,
+    pub static_mapping: BTreeMap<String, OpnFiSystemConfigStaticMapping>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+#[serde(default)]
+pub struct OpnFiSystemConfigDhcpRange {
+    pub stop: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct OpnFiSystemConfigStaticMapping {
+    pub ip_address: String,
+    pub mac_address: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+#[serde(default)]
+pub struct OpnFiSystemConfigDns {
+    pub forwarding: OpnFiSystemConfigDnsForwarding,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct OpnFiSystemConfigDnsForwarding {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_size: Option<String>,
+    pub except_interface: Vec<String>,
+    pub name_server: Vec<String>,
+    pub options: Vec<String>,
+}
+
+// ===== System =====
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct OpnFiSystemConfigSystem {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub domain_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host_name: Option<String>,
+    pub name_server: Vec<String>,
+}
+
+// ===== LAN Networks =====
+
+/// A provisioned LAN network with its DHCP settings.
+#[derive(PartialEq, Clone, Debug)]
+pub struct OpnFiLanNetwork {
+    pub name: String,
+    pub enabled: bool,
+    pub subnet: String,
+    pub router: Option<String>,
+    pub dns_servers: Vec<String>,
+    pub domain_name: Option<String>,
+    /// Lease time in seconds
+    pub lease_time: Option<u64>,
+    /// DHCP pool start and stop addresses
+    pub ranges: Vec<(String, String)>,
+    pub static_leases: Vec<OpnFiStaticLease>,
+}
+
+/// Fixed IP assigned to a client
+#[derive(PartialEq, Clone, Debug)]
+pub struct OpnFiStaticLease {
+    pub name: String,
+    pub mac: String,
+    pub ip: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error;
+
+    type TestResult = std::result::Result<(), Box<dyn error::Error + 'static>>;
+
+    const SYSTEM_CFG: &str = r#"{
+        "interfaces": {
+            "ethernet": {
+                "eth0": { "address": ["dhcp"], "description": "WAN" },
+                "eth1": { "address": ["192.168.1.1/24"], "description": "LAN" }
+            }
+        },
+        "service": {
+            "dhcp-server": {
+                "disabled": "false",
+                "shared-network-name": {
+                    "net_LAN_eth1_192.168.1.0-24": {
+                        "authoritative": "enable",
+                        "subnet": {
+                            "192.168.1.0/24": {
+                                "default-router": "192.168.1.1",
+                                "dns-server": ["192.168.1.1"],
+                                "lease": "86400",
+                                "start": { "192.168.1.6": { "stop": "192.168.1.254" } },
+                                "static-mapping": {
+                                    "printer": {
+                                        "ip-address": "192.168.1.10",
+                                        "mac-address": "00:de:ad:be:ef:00"
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            },
+            "dns": {
+                "forwarding": {
+                    "cache-size": "10000",
+                    "except-interface": ["eth0"],
+                    "name-server": ["1.1.1.1", "9.9.9.9"]
+                }
+            }
+        },
+        "system": { "domain-name": "localdomain", "host-name": "ubnt" }
+    }"#;
+
+    #[test]
+    fn test_parse_system_cfg() -> TestResult {
+        let cfg = OpnFiSystemConfig::from_json(SYSTEM_CFG)?;
+        assert_eq!(
+            cfg.interfaces.ethernet["eth1"].address,
+            vec!["192.168.1.1/24".to_string()]
+        );
+        assert_eq!(
+            cfg.service.dns.forwarding.name_server,
+            vec!["1.1.1.1".to_string(), "9.9.9.9".to_string()]
+        );
+        assert_eq!(cfg.system.domain_name, Some("localdomain".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_lan_networks() -> TestResult {
+        let cfg = OpnFiSystemConfig::from_json(SYSTEM_CFG)?;
+        let networks = cfg.lan_networks();
+        assert_eq!(networks.len(), 1);
+        let lan = &networks[0];
+        assert_eq!(lan.subnet, "192.168.1.0/24");
+        assert_eq!(lan.router, Some("192.168.1.1".to_string()));
+        assert_eq!(lan.domain_name, Some("localdomain".to_string()));
+        assert_eq!(lan.lease_time, Some(86400));
+        assert_eq!(
+            lan.ranges,
+            vec![("192.168.1.6".to_string(), "192.168.1.254".to_string())]
+        );
+        assert_eq!(
+            lan.static_leases,
+            vec![OpnFiStaticLease {
+                name: "printer".to_string(),
+                mac: "00:de:ad:be:ef:00".to_string(),
+                ip: "192.168.1.10".to_string(),
+            }]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,511 @@
+use super::adoption::{OpnFiAdoptionState, OpnFiInformEvent};
+use super::payload::command::{
+    parse_mgmt_cfg, OpnFiInformPayloadCmdCommand, OpnFiInformPayloadCommand,
+    OpnFiInformPayloadSetParamsCommand, OpnFiRebootType,
+};
+use super::payload::gateway::OpnFiDeviceState;
+use super::payload::OpnFiInformPayload;
+use super::{OpnFiReadExt, OpnFiWriteExt, OpnfiInformPacket, OpnfiInformPacketFlag};
+use crate::error::OpnFiError;
+use crate::Result;
+use byteorder::NetworkEndian;
+use pnet::util::MacAddr;
+use rand::prelude::*;
+use std::collections::VecDeque;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// Interval between informs until the controller asks for another one
+pub const DEFAULT_INFORM_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Shortest response that can hold a packet
+const MIN_RESPONSE_LENGTH: usize = 40;
+
+// ===== Actions =====
+
+/// What the device should do next, see [`OpnFiDeviceProtocol::poll_action`].
+#[derive(PartialEq, Clone, Debug)]
+pub enum OpnFiDeviceAction {
+    /// An inform is due, build a payload and pass it to `send_inform`
+    Inform,
+    /// Post these bytes to the inform URL and pass the answer back
+    Transmit(Vec<u8>),
+    /// Apply and persist new parameters, the protocol already took the auth key from `mgmt_cfg`
+    ApplyConfig(OpnFiInformPayloadSetParamsCommand),
+    /// Forget the saved config, the device was removed from the controller
+    SetDefault,
+    /// Reboot once the next inform went out
+    Reboot(OpnFiRebootType),
+    /// Run a `cmd` command
+    Cmd(OpnFiInformPayloadCmdCommand),
+    /// A command the protocol doesn't know how to handle
+    Unhandled(OpnFiInformPayloadCommand),
+    StateChanged(OpnFiDeviceState),
+}
+
+// ===== Protocol =====
+
+/// The inform protocol of a device without any IO.
+///
+/// Feed it timeouts and controller responses, then drain `poll_action` and carry out the
+/// actions. It keeps the adoption state, auth key, cfgversion and inform interval.
+pub struct OpnFiDeviceProtocol {
+    hardware_address: MacAddr,
+    adoption: OpnFiAdoptionState,
+    authkey: Option<[u8; 16]>,
+    use_aes_gcm: bool,
+    cfgversion: Option<String>,
+    interval: Duration,
+    /// Time of the last inform, None until the first one
+    last_inform: Option<Instant>,
+    /// The next inform should go out right away
+    inform_now: bool,
+    actions: VecDeque<OpnFiDeviceAction>,
+    rng: StdRng,
+}
+
+impl OpnFiDeviceProtocol {
+    /// Protocol for a device that isn't adopted yet, informs are encrypted with the master key.
+    pub fn new(hardware_address: MacAddr) -> Self {
+        OpnFiDeviceProtocol {
+            hardware_address,
+            adoption: OpnFiAdoptionState::new(false),
+            authkey: None,
+            use_aes_gcm: false,
+            cfgversion: None,
+            interval: DEFAULT_INFORM_INTERVAL,
+            last_inform: None,
+            inform_now: true,
+            actions: VecDeque::new(),
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    pub fn set_hardware_address(&mut self, hardware_address: MacAddr) {
+        self.hardware_address = hardware_address;
+    }
+
+    /// Use the hex encoded `authkey` of a `mgmt_cfg`, None goes back to the master key.
+    /// The adoption state starts over as connected or pending adoption.
+    pub fn set_authkey(&mut self, authkey: Option<&str>) -> Result<()> {
+        self.authkey = match authkey {
+            Some(authkey) => Some(decode_authkey(authkey)?),
+            None => None,
+        };
+        self.adoption = OpnFiAdoptionState::new(self.is_adopted());
+        Ok(())
+    }
+
+    pub fn set_use_aes_gcm(&mut self, use_aes_gcm: bool) {
+        self.use_aes_gcm = use_aes_gcm;
+    }
+
+    /// Version of the config the device runs, reported in gateway informs
+    pub fn set_cfgversion(&mut self, cfgversion: Option<&str>) {
+        self.cfgversion = cfgversion.map(String::from);
+    }
+
+    pub fn state(&self) -> OpnFiDeviceState {
+        self.adoption.state()
+    }
+
+    /// Whether the device holds an auth key from the controller
+    pub fn is_adopted(&self) -> bool {
+        self.authkey.is_some()
+    }
+
+    pub fn cfgversion(&self) -> Option<&str> {
+        self.cfgversion.as_deref()
+    }
+
+    /// Time to wait between informs
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Inform on the next timeout instead of waiting for the interval
+    pub fn inform_now(&mut self) {
+        self.inform_now = true;
+    }
+
+    /// When `handle_timeout` should be called next
+    pub fn poll_timeout(&self) -> Option<Instant> {
+        match self.last_inform {
+            Some(last_inform) if !self.inform_now => Some(last_inform + self.interval),
+            _ => None,
+        }
+    }
+
+    /// Queue an inform if one is due, `None` from `poll_timeout` means one is due right away
+    pub fn handle_timeout(&mut self, now: Instant) {
+        let due = match self.poll_timeout() {
+            Some(timeout) => timeout <= now,
+            None => true,
+        };
+        if due {
+            self.inform_now = false;
+            self.last_inform = Some(now);
+            self.actions.push_back(OpnFiDeviceAction::Inform);
+        }
+    }
+
+    /// Encrypt a payload and queue it for transmission.
+    /// Gateway payloads get the adoption state and, once known, the cfgversion.
+    pub fn send_inform(&mut self, mut payload: OpnFiInformPayload, now: Instant) -> Result<()> {
+        if let OpnFiInformPayload::Gateway(gateway) = &mut payload {
+            gateway.state = self.adoption.reported(self.is_adopted());
+            if let Some(cfgversion) = &self.cfgversion {
+                gateway.cfgversion = cfgversion.clone();
+            }
+        }
+        let mut flags = OpnfiInformPacketFlag::Encrypted | OpnfiInformPacketFlag::ZLibCompressed;
+        if self.use_aes_gcm {
+            flags |= OpnfiInformPacketFlag::EncryptedGCM;
+        }
+        let mut initialization_vector = [0u8; 16];
+        self.rng.fill_bytes(&mut initialization_vector);
+
+        let packet =
+            OpnfiInformPacket::new(None, 0, self.hardware_address, flags.bits(), 1, payload);
+        let mut data = Vec::new();
+        packet.write::<NetworkEndian>(self.authkey, initialization_vector, &mut data)?;
+        self.inform_now = false;
+        self.last_inform = Some(now);
+        self.actions.push_back(OpnFiDeviceAction::Transmit(data));
+        Ok(())
+    }
+
+    /// Decode the body of a successful response and queue what the controller asked for.
+    /// Bodies too short to hold a packet carry no command.
+    pub fn handle_response(&mut self, body: &[u8]) -> Result<()> {
+        if body.len() < MIN_RESPONSE_LENGTH {
+            self.transition(OpnFiInformEvent::NoOp);
+            return Ok(());
+        }
+        let packet: Result<OpnfiInformPacket<OpnFiInformPayload>> =
+            OpnfiInformPacket::read::<NetworkEndian>(
+                self.authkey,
+                None,
+                &mut io::Cursor::new(body),
+            );
+        match packet.map(|packet| packet.payload) {
+            Ok(OpnFiInformPayload::Command(command)) => {
+                self.handle_command(command);
+                Ok(())
+            }
+            Ok(_) => {
+                self.handle_error(OpnFiInformEvent::InvalidResponse);
+                Err(OpnFiError::InvalidData)
+            }
+            Err(e) => {
+                self.handle_error(OpnFiInformEvent::InvalidResponse);
+                Err(e)
+            }
+        }
+    }
+
+    /// The controller answered with an unsuccessful HTTP status
+    pub fn handle_rejected(&mut self) {
+        self.handle_error(OpnFiInformEvent::Rejected);
+    }
+
+    /// The inform could not be delivered
+    pub fn handle_unreachable(&mut self) {
+        self.handle_error(OpnFiInformEvent::Unreachable);
+    }
+
+    /// Next action to carry out, in the order they were queued
+    pub fn poll_action(&mut self) -> Option<OpnFiDeviceAction> {
+        self.actions.pop_front()
+    }
+
+    fn handle_error(&mut self, event: OpnFiInformEvent) {
+        self.interval = DEFAULT_INFORM_INTERVAL;
+        self.transition(event);
+    }
+
+    fn handle_command(&mut self, command: OpnFiInformPayloadCommand) {
+        match command {
+            OpnFiInformPayloadCommand::NoOp(noop) => {
+                self.interval = noop.interval();
+                self.transition(OpnFiInformEvent::NoOp);
+            }
+            OpnFiInformPayloadCommand::SetParam(params) => {
+                self.transition(OpnFiInformEvent::SetParam {
+                    mgmt_cfg: params.mgmt_cfg.is_some(),
+                });
+                if let Some(mgmt_cfg) = &params.mgmt_cfg {
+                    self.apply_mgmt_cfg(mgmt_cfg);
+                }
+                self.inform_now = true;
+                self.actions
+                    .push_back(OpnFiDeviceAction::ApplyConfig(params));
+            }
+            OpnFiInformPayloadCommand::SetDefault(_) => {
+                self.transition(OpnFiInformEvent::SetDefault);
+                self.authkey = None;
+                self.use_aes_gcm = false;
+                self.cfgversion = None;
+                self.inform_now = true;
+                self.actions.push_back(OpnFiDeviceAction::SetDefault);
+            }
+            OpnFiInformPayloadCommand::Reboot(reboot) => {
+                self.transition(OpnFiInformEvent::Command);
+                self.inform_now = true;
+                self.actions
+                    .push_back(OpnFiDeviceAction::Reboot(reboot.reboot_type()));
+            }
+            OpnFiInformPayloadCommand::Cmd(cmd) => {
+                self.transition(OpnFiInformEvent::Command);
+                self.inform_now = true;
+                self.actions.push_back(OpnFiDeviceAction::Cmd(cmd));
+            }
+            command => {
+                self.transition(OpnFiInformEvent::Command);
+                self.actions
+                    .push_back(OpnFiDeviceAction::Unhandled(command));
+            }
+        }
+    }
+
+    /// Take the auth key, cipher and cfgversion from a `mgmt_cfg`
+    fn apply_mgmt_cfg(&mut self, mgmt_cfg: &str) {
+        for (key, value) in parse_mgmt_cfg(mgmt_cfg) {
+            match key {
+                // A malformed key leaves the previous one in place
+                "authkey" => {
+                    if let Ok(authkey) = decode_authkey(value) {
+                        self.authkey = Some(authkey);
+                    }
+                }
+                "use_aes_gcm" => self.use_aes_gcm = value == "true",
+                "cfgversion" => self.cfgversion = Some(value.to_string()),
+                _ => {}
+            }
+        }
+    }
+
+    /// Move to the state following an inform
+    fn transition(&mut self, event: OpnFiInformEvent) {
+        let adopted = self.is_adopted();
+        if let Some(state) = self.adoption.transition(event, adopted) {
+            self.actions
+                .push_back(OpnFiDeviceAction::StateChanged(state));
+        }
+    }
+}
+
+/// Decode a 32 digit hex auth key
+fn decode_authkey(authkey: &str) -> Result<[u8; 16]> {
+    if authkey.len() != 32 || !authkey.is_ascii() {
+        return Err(OpnFiError::InvalidInput);
+    }
+    let mut key = [0u8; 16];
+    for (index, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&authkey[index * 2..index * 2 + 2], 16)
+            .map_err(|_| OpnFiError::InvalidInput)?;
+    }
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inform::payload::command::OpnFiInformCmd;
+    use crate::inform::payload::gateway::OpnFiInformGatewayPayload;
+    use std::error;
+    use OpnFiDeviceState::*;
+
+    type TestResult = std::result::Result<(), Box<dyn error::Error + 'static>>;
+
+    const AUTHKEY: &str = "0123456789abcdef0123456789abcdef";
+
+    /// Encode a controller response the way the controller would
+    fn response(key: Option<[u8; 16]>, json: &str) -> Result<Vec<u8>> {
+        let payload: OpnFiInformPayload = serde_json::from_str(json)?;
+        let packet = OpnfiInformPacket::new(None, 0, MacAddr::zero(), 0x3, 1, payload);
+        let mut data = Vec::new();
+        packet.write::<NetworkEndian>(key, [7u8; 16], &mut data)?;
+        Ok(data)
+    }
+
+    fn actions(protocol: &mut OpnFiDeviceProtocol) -> Vec<OpnFiDeviceAction> {
+        std::iter::from_fn(|| protocol.poll_action()).collect()
+    }
+
+    /// Run one inform, returns the packet the device sent
+    fn inform(
+        protocol: &mut OpnFiDeviceProtocol,
+        now: Instant,
+    ) -> Result<OpnfiInformPacket<OpnFiInformPayload>> {
+        let key = protocol.authkey;
+        let payload = OpnFiInformPayload::Gateway(OpnFiInformGatewayPayload::default());
+        protocol.send_inform(payload, now)?;
+        match protocol.poll_action() {
+            Some(OpnFiDeviceAction::Transmit(data)) => {
+                OpnfiInformPacket::read::<NetworkEndian>(key, None, &mut io::Cursor::new(data))
+            }
+            action => panic!("Expected a transmit, got {:?}", action),
+        }
+    }
+
+    #[test]
+    fn test_adoption() -> TestResult {
+        let start = Instant::now();
+        let mut protocol = OpnFiDeviceProtocol::new(MacAddr::zero());
+        protocol.handle_timeout(start);
+        assert_eq!(actions(&mut protocol), vec![OpnFiDeviceAction::Inform]);
+        assert_eq!(protocol.state(), PendingAdoption);
+        let packet = inform(&mut protocol, start)?;
+        match packet.payload {
+            OpnFiInformPayload::Gateway(gateway) => assert_eq!(gateway.state, PendingAdoption),
+            payload => panic!("Unexpected payload {:?}", payload),
+        }
+        protocol.handle_rejected();
+        assert_eq!(actions(&mut protocol), Vec::new());
+
+        // Adopted in the controller, it answers with the auth key
+        let mgmt_cfg = format!("authkey={}\\nuse_aes_gcm=true\\ncfgversion=a1b2", AUTHKEY);
+        let body = response(
+            None,
+            &format!(
+                r#"{{"_type": "setparam", "mgmt_cfg": "{}", "server_time_in_utc": "0"}}"#,
+                mgmt_cfg
+            ),
+        )?;
+        protocol.handle_response(&body)?;
+        let actions = actions(&mut protocol);
+        assert_eq!(actions[0], OpnFiDeviceAction::StateChanged(Adopting));
+        assert!(matches!(actions[1], OpnFiDeviceAction::ApplyConfig(_)));
+        assert!(protocol.is_adopted());
+        assert_eq!(protocol.cfgversion(), Some("a1b2"));
+        // Informs now go out with the new key right away
+        assert_eq!(protocol.poll_timeout(), None);
+        let packet = inform(&mut protocol, start)?;
+        assert_eq!(packet.flags, 0xb);
+        match packet.payload {
+            OpnFiInformPayload::Gateway(gateway) => {
+                assert_eq!(gateway.state, Adopting);
+                assert_eq!(gateway.cfgversion, "a1b2");
+            }
+            payload => panic!("Unexpected payload {:?}", payload),
+        }
+
+        let body = response(
+            protocol.authkey,
+            r#"{"_type": "noop", "interval": 30, "server_time_in_utc": "0"}"#,
+        )?;
+        protocol.handle_response(&body)?;
+        assert_eq!(protocol.state(), Connected);
+        assert_eq!(protocol.interval(), Duration::from_secs(30));
+        assert_eq!(
+            protocol.poll_timeout(),
+            Some(start + Duration::from_secs(30))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_timeout() {
+        let start = Instant::now();
+        let mut protocol = OpnFiDeviceProtocol::new(MacAddr::zero());
+        protocol.handle_timeout(start);
+        protocol.handle_timeout(start + Duration::from_secs(5));
+        assert_eq!(actions(&mut protocol), vec![OpnFiDeviceAction::Inform]);
+        protocol.handle_timeout(start + DEFAULT_INFORM_INTERVAL);
+        assert_eq!(actions(&mut protocol), vec![OpnFiDeviceAction::Inform]);
+    }
+
+    #[test]
+    fn test_commands() -> TestResult {
+        let mut protocol = OpnFiDeviceProtocol::new(MacAddr::zero());
+        protocol.set_authkey(Some(AUTHKEY))?;
+        let key = protocol.authkey;
+        protocol.handle_response(&response(
+            key,
+            r#"{"_type": "cmd", "_id": "1", "cmd": "set-locate", "date_time": "", "device_id": "", "server_time_in_utc": "0", "time": 0, "use_alert": true}"#,
+        )?)?;
+        assert_eq!(protocol.state(), Connected);
+        let actions_after_cmd = actions(&mut protocol);
+        assert_eq!(actions_after_cmd.len(), 1);
+        match &actions_after_cmd[0] {
+            OpnFiDeviceAction::Cmd(cmd) => assert_eq!(cmd.command(), OpnFiInformCmd::SetLocate),
+            action => panic!("Unexpected action {:?}", action),
+        }
+
+        protocol.handle_response(&response(
+            key,
+            r#"{"_type": "setdefault", "server_time_in_utc": "0"}"#,
+        )?)?;
+        assert_eq!(
+            actions(&mut protocol),
+            vec![
+                OpnFiDeviceAction::StateChanged(Deleting),
+                OpnFiDeviceAction::SetDefault
+            ]
+        );
+        assert!(!protocol.is_adopted());
+        Ok(())
+    }
+
+    #[test]
+    fn test_adoption_failed() -> TestResult {
+        let mut protocol = OpnFiDeviceProtocol::new(MacAddr::zero());
+        protocol.handle_response(&response(
+            None,
+            &format!(
+                r#"{{"_type": "setparam", "mgmt_cfg": "authkey={}", "server_time_in_utc": "0"}}"#,
+                AUTHKEY
+            ),
+        )?)?;
+        assert_eq!(protocol.state(), Adopting);
+        // Still answered with the master key
+        let body = response(
+            None,
+            r#"{"_type": "noop", "interval": 30, "server_time_in_utc": "0"}"#,
+        )?;
+        assert!(protocol.handle_response(&body).is_err());
+        assert_eq!(protocol.state(), AdoptionFailed);
+        assert_eq!(protocol.interval(), DEFAULT_INFORM_INTERVAL);
+        protocol.handle_rejected();
+        assert_eq!(protocol.state(), AdoptionFailed);
+        Ok(())
+    }
+
+    #[test]
+    fn test_heartbeat_missed() -> TestResult {
+        let mut protocol = OpnFiDeviceProtocol::new(MacAddr::zero());
+        protocol.set_authkey(Some(AUTHKEY))?;
+        assert_eq!(protocol.state(), Connected);
+        protocol.handle_unreachable();
+        protocol.handle_unreachable();
+        assert_eq!(protocol.state(), HeartbeatMissed);
+        protocol.handle_unreachable();
+        assert_eq!(protocol.state(), Disconnected);
+        assert_eq!(
+            actions(&mut protocol),
+            vec![
+                OpnFiDeviceAction::StateChanged(HeartbeatMissed),
+                OpnFiDeviceAction::StateChanged(Disconnected)
+            ]
+        );
+        // An inform that gets through reports the device as connected
+        let packet = inform(&mut protocol, Instant::now())?;
+        match packet.payload {
+            OpnFiInformPayload::Gateway(gateway) => assert_eq!(gateway.state, Connected),
+            payload => panic!("Unexpected payload {:?}", payload),
+        }
+        protocol.handle_response(&[])?;
+        assert_eq!(protocol.state(), Connected);
+        protocol.handle_rejected();
+        assert_eq!(protocol.state(), InformError);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_authkey() {
+        assert_eq!(decode_authkey(AUTHKEY).unwrap()[..2], [0x01, 0x23]);
+        assert!(decode_authkey("0123").is_err());
+        assert!(decode_authkey("0123456789abcdef0123456789abcdeg").is_err());
+    }
+}
@@ -6,6 +6,7 @@ extern crate serde_json;
 pub mod discovery;
 pub mod error;
 pub mod inform;
+pub mod model;
 mod tlv;
 pub mod util;
 
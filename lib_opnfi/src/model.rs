@@ -0,0 +1,187 @@
+use crate::inform::payload::gateway::{OpnFiFirewallCap, OpnFiRadiusCap};
+use enumflags2::BitFlags;
+
+// ===== Model Profiles =====
+
+/// A physical port of a model, as the controller expects it in `config_port_table`.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct OpnFiModelPort {
+    /// Port name, e.g. WAN, WAN2 or LAN
+    pub name: &'static str,
+    pub ifname: &'static str,
+}
+
+/// A UniFi gateway model that can be emulated.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct OpnFiModelProfile {
+    /// Model code, e.g. UGW3
+    pub model: &'static str,
+    pub display_name: &'static str,
+    /// Capabilities the model advertises
+    pub fw_caps: &'static [OpnFiFirewallCap],
+    pub radius_caps: &'static [OpnFiRadiusCap],
+    /// Ports in the order the model lists them
+    pub ports: &'static [OpnFiModelPort],
+    /// Oldest firmware the controller should expect on the model
+    pub required_version: &'static str,
+}
+
+/// Every firewall capability a UniFi Security Gateway advertises
+const USG_FW_CAPS: &[OpnFiFirewallCap] = &[
+    OpnFiFirewallCap::Dpi,
+    OpnFiFirewallCap::DnsmasqHostfileUpdate,
+    OpnFiFirewallCap::DefaultRouteDistance,
+    OpnFiFirewallCap::SshDisable,
+    OpnFiFirewallCap::Vti,
+    OpnFiFirewallCap::SpeedTest,
+    OpnFiFirewallCap::Ipv6,
+    OpnFiFirewallCap::LoadBalance,
+    OpnFiFirewallCap::Offload,
+];
+
+const fn port(name: &'static str, ifname: &'static str) -> OpnFiModelPort {
+    OpnFiModelPort { name, ifname }
+}
+
+impl OpnFiModelProfile {
+    pub const DEFAULT_MODEL: &'static str = "UGWXG";
+
+    /// Every known model
+    pub const ALL: &'static [OpnFiModelProfile] = &[
+        OpnFiModelProfile {
+            model: "UGW3",
+            display_name: "UniFi Security Gateway 3P",
+            fw_caps: USG_FW_CAPS,
+            radius_caps: &[OpnFiRadiusCap::Server, OpnFiRadiusCap::Accounting],
+            ports: &[
+                port("WAN", "eth0"),
+                port("LAN", "eth1"),
+                port("WAN2", "eth2"),
+            ],
+            required_version: "4.4.44",
+        },
+        OpnFiModelProfile {
+            model: "UGW4",
+            display_name: "UniFi Security Gateway 4P",
+            fw_caps: USG_FW_CAPS,
+            radius_caps: &[OpnFiRadiusCap::Server, OpnFiRadiusCap::Accounting],
+            ports: &[
+                port("LAN", "eth0"),
+                port("LAN2", "eth1"),
+                port("WAN", "eth2"),
+                port("WAN2", "eth3"),
+            ],
+            required_version: "4.4.44",
+        },
+        OpnFiModelProfile {
+            model: "UGWXG",
+            display_name: "UniFi Security Gateway XG-8",
+            fw_caps: &[
+                OpnFiFirewallCap::Dpi,
+                OpnFiFirewallCap::DnsmasqHostfileUpdate,
+                OpnFiFirewallCap::DefaultRouteDistance,
+                OpnFiFirewallCap::SshDisable,
+                OpnFiFirewallCap::Vti,
+                OpnFiFirewallCap::SpeedTest,
+                OpnFiFirewallCap::Ipv6,
+                OpnFiFirewallCap::LoadBalance,
+                OpnFiFirewallCap::Offload,
+                OpnFiFirewallCap::Porta,
+            ],
+            radius_caps: &[OpnFiRadiusCap::Server, OpnFiRadiusCap::Accounting],
+            ports: &[
+                port("WAN", "eth0"),
+                port("LAN", "eth1"),
+                port("LAN2", "eth2"),
+                port("LAN3", "eth3"),
+                port("LAN4", "eth4"),
+                port("LAN5", "eth5"),
+                port("LAN6", "eth6"),
+                port("LAN7", "eth7"),
+                port("WAN2", "eth8"),
+            ],
+            required_version: "4.4.44",
+        },
+        OpnFiModelProfile {
+            model: "UXGPRO",
+            display_name: "UniFi Next-Generation Gateway Pro",
+            fw_caps: &[
+                OpnFiFirewallCap::Dpi,
+                OpnFiFirewallCap::DnsmasqHostfileUpdate,
+                OpnFiFirewallCap::DefaultRouteDistance,
+                OpnFiFirewallCap::SshDisable,
+                OpnFiFirewallCap::SpeedTest,
+                OpnFiFirewallCap::Ipv6,
+                OpnFiFirewallCap::LoadBalance,
+                OpnFiFirewallCap::Offload,
+            ],
+            radius_caps: &[OpnFiRadiusCap::Server],
+            ports: &[
+                port("WAN", "eth0"),
+                port("LAN", "eth1"),
+                port("WAN2", "eth2"),
+                port("LAN2", "eth3"),
+            ],
+            required_version: "1.11.0",
+        },
+    ];
+
+    /// Look up a model by its code, ignoring case
+    pub fn find(model: &str) -> Option<&'static OpnFiModelProfile> {
+        Self::ALL
+            .iter()
+            .find(|profile| profile.model.eq_ignore_ascii_case(model))
+    }
+
+    pub fn fw_cap_flags(&self) -> BitFlags<OpnFiFirewallCap> {
+        self.fw_caps.iter().copied().collect()
+    }
+
+    pub fn radius_cap_flags(&self) -> BitFlags<OpnFiRadiusCap> {
+        self.radius_caps.iter().copied().collect()
+    }
+
+    /// The port of the model with a name, ignoring case
+    pub fn port(&self, name: &str) -> Option<&'static OpnFiModelPort> {
+        self.ports
+            .iter()
+            .find(|port| port.name.eq_ignore_ascii_case(name))
+    }
+}
+
+impl Default for OpnFiModelProfile {
+    fn default() -> Self {
+        *Self::find(Self::DEFAULT_MODEL).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find() {
+        let usg = OpnFiModelProfile::find("ugw3").unwrap();
+        assert_eq!(usg.display_name, "UniFi Security Gateway 3P");
+        assert_eq!(usg.port("wan2").map(|port| port.ifname), Some("eth2"));
+        assert_eq!(usg.port("LAN2"), None);
+        assert!(OpnFiModelProfile::find("UAP").is_none());
+        assert_eq!(usg.fw_cap_flags().bits(), 0x3df);
+        assert_eq!(usg.radius_cap_flags().bits(), 0x3);
+        assert_eq!(OpnFiModelProfile::default().model, "UGWXG");
+    }
+
+    #[test]
+    fn test_profiles() {
+        for profile in OpnFiModelProfile::ALL {
+            // Every model has a WAN and a LAN, and no port twice
+            assert!(profile.port("WAN").is_some(), "{}", profile.model);
+            assert!(profile.port("LAN").is_some(), "{}", profile.model);
+            for (index, port) in profile.ports.iter().enumerate() {
+                assert!(profile.ports[index + 1..]
+                    .iter()
+                    .all(|other| other.name != port.name && other.ifname != port.ifname));
+            }
+        }
+    }
+}
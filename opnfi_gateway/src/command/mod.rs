@@ -0,0 +1,125 @@
+use lib_opnfi::inform::payload::command::OpnFiInformCmd;
+use std::collections::HashMap;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use std::{error, fmt, io};
+
+pub(crate) mod reboot;
+
+// ===== Handlers =====
+
+/// Handles one or more `cmd` commands sent by the controller.
+pub(crate) trait CmdHandler {
+    fn handle(&mut self, cmd: &OpnFiInformCmd) -> io::Result<()>;
+}
+
+impl<F: FnMut(&OpnFiInformCmd) -> io::Result<()>> CmdHandler for F {
+    fn handle(&mut self, cmd: &OpnFiInformCmd) -> io::Result<()> {
+        self(cmd)
+    }
+}
+
+/// Turns the locate flag reported in the inform on and off.
+pub(crate) struct LocateHandler {
+    locating: Arc<AtomicBool>,
+}
+
+impl LocateHandler {
+    pub fn new(locating: Arc<AtomicBool>) -> Self {
+        LocateHandler { locating }
+    }
+}
+
+impl CmdHandler for LocateHandler {
+    fn handle(&mut self, cmd: &OpnFiInformCmd) -> io::Result<()> {
+        match cmd {
+            OpnFiInformCmd::SetLocate => self.locating.store(true, Ordering::SeqCst),
+            OpnFiInformCmd::UnsetLocate => self.locating.store(false, Ordering::SeqCst),
+            cmd => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("LocateHandler can not handle {}", cmd.name()),
+                ))
+            }
+        }
+        Ok(())
+    }
+}
+
+// ===== Registry =====
+
+#[derive(Debug)]
+pub(crate) enum CmdError {
+    /// No handler is registered for the command
+    Unsupported(String),
+    /// The handler failed to run the command
+    Failed(String, io::Error),
+}
+
+impl fmt::Display for CmdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CmdError::Unsupported(cmd) => write!(f, "[CmdError::Unsupported] {}", cmd),
+            CmdError::Failed(cmd, e) => write!(f, "[CmdError::Failed] {}: {}", cmd, e),
+        }
+    }
+}
+
+impl error::Error for CmdError {}
+
+/// Dispatches `cmd` commands to the handler registered for their name.
+#[derive(Default)]
+pub(crate) struct CmdRegistry {
+    handlers: HashMap<String, Box<dyn CmdHandler>>,
+}
+
+impl CmdRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a handler for a command name, replacing any existing handler.
+    pub fn register<H: CmdHandler + 'static>(&mut self, name: &str, handler: H) {
+        self.handlers.insert(name.to_string(), Box::new(handler));
+    }
+
+    pub fn dispatch(&mut self, cmd: &OpnFiInformCmd) -> Result<(), CmdError> {
+        let name = cmd.name().to_string();
+        match self.handlers.get_mut(&name) {
+            Some(handler) => handler.handle(cmd).map_err(|e| CmdError::Failed(name, e)),
+            None => Err(CmdError::Unsupported(name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dispatch() {
+        let locating = Arc::new(AtomicBool::new(false));
+        let mut registry = CmdRegistry::new();
+        registry.register("set-locate", LocateHandler::new(locating.clone()));
+        registry.register("unset-locate", LocateHandler::new(locating.clone()));
+        registry.register("restart-provision", |_: &OpnFiInformCmd| {
+            Err(io::Error::other("busy"))
+        });
+
+        assert!(registry.dispatch(&OpnFiInformCmd::SetLocate).is_ok());
+        assert!(locating.load(Ordering::SeqCst));
+        assert!(registry.dispatch(&OpnFiInformCmd::UnsetLocate).is_ok());
+        assert!(!locating.load(Ordering::SeqCst));
+
+        match registry.dispatch(&OpnFiInformCmd::RestartProvision) {
+            Err(CmdError::Failed(cmd, _)) => assert_eq!(cmd, "restart-provision"),
+            result => panic!("Unexpected result {:?}", result),
+        }
+        match registry.dispatch(&OpnFiInformCmd::Unknown("self-destruct".to_string())) {
+            Err(CmdError::Unsupported(cmd)) => assert_eq!(cmd, "self-destruct"),
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+}
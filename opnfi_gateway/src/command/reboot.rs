@@ -0,0 +1,107 @@
+use crate::settings::RebootSettings;
+use lib_opnfi::inform::payload::command::OpnFiRebootType;
+use std::{io, process};
+
+/// Performs a reboot requested by the controller.
+pub(crate) trait RebootExecutor {
+    /// Gracefully restart the system
+    fn soft_reboot(&mut self) -> io::Result<()>;
+
+    /// Restart the system immediately without a clean shutdown
+    fn hard_reboot(&mut self) -> io::Result<()>;
+
+    fn reboot(&mut self, reboot_type: OpnFiRebootType) -> io::Result<()> {
+        match reboot_type {
+            OpnFiRebootType::Soft => self.soft_reboot(),
+            OpnFiRebootType::Hard => self.hard_reboot(),
+        }
+    }
+}
+
+/// Build the executor selected in the settings.
+pub(crate) fn executor(settings: &RebootSettings) -> Box<dyn RebootExecutor> {
+    if settings.dry_run {
+        Box::new(DryRunRebootExecutor::default())
+    } else {
+        Box::new(SystemRebootExecutor::new(settings.clone()))
+    }
+}
+
+// ===== Dry Run =====
+
+/// Only logs and records the requested reboots.
+#[derive(Default, Debug)]
+pub(crate) struct DryRunRebootExecutor {
+    pub requested: Vec<OpnFiRebootType>,
+}
+
+impl RebootExecutor for DryRunRebootExecutor {
+    fn soft_reboot(&mut self) -> io::Result<()> {
+        info!("Dry run: soft reboot requested");
+        self.requested.push(OpnFiRebootType::Soft);
+        Ok(())
+    }
+
+    fn hard_reboot(&mut self) -> io::Result<()> {
+        info!("Dry run: hard reboot requested");
+        self.requested.push(OpnFiRebootType::Hard);
+        Ok(())
+    }
+}
+
+// ===== System =====
+
+/// Reboots the host with the configured commands.
+pub(crate) struct SystemRebootExecutor {
+    settings: RebootSettings,
+}
+
+impl SystemRebootExecutor {
+    pub fn new(settings: RebootSettings) -> Self {
+        SystemRebootExecutor { settings }
+    }
+
+    fn run(command: &[String]) -> io::Result<()> {
+        let (program, args) = command
+            .split_first()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Empty reboot command"))?;
+        let status = process::Command::new(program).args(args).status()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!(
+                "{} exited with {}",
+                program, status
+            )))
+        }
+    }
+}
+
+impl RebootExecutor for SystemRebootExecutor {
+    fn soft_reboot(&mut self) -> io::Result<()> {
+        warn!("Soft rebooting system");
+        Self::run(&self.settings.soft_command)
+    }
+
+    fn hard_reboot(&mut self) -> io::Result<()> {
+        warn!("Hard rebooting system");
+        Self::run(&self.settings.hard_command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dry_run_reboot() -> io::Result<()> {
+        let mut executor = DryRunRebootExecutor::default();
+        executor.reboot(OpnFiRebootType::Soft)?;
+        executor.reboot(OpnFiRebootType::Hard)?;
+        assert_eq!(
+            executor.requested,
+            vec![OpnFiRebootType::Soft, OpnFiRebootType::Hard]
+        );
+        Ok(())
+    }
+}
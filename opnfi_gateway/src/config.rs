@@ -1,3 +1,4 @@
+use lib_opnfi::inform::payload::command::parse_mgmt_cfg;
 use serde::{Deserialize, Serialize};
 use std::{
     fs,
@@ -37,9 +38,8 @@ impl Config {
     }
 
     pub fn update_from_mgmt_cfg(&mut self, mgmt_cfg: String) {
-        for cfg_line in mgmt_cfg.lines() {
-            let pair: Vec<_> = cfg_line.splitn(2, "=").collect();
-            match (pair[0], pair[1]) {
+        for pair in parse_mgmt_cfg(&mgmt_cfg) {
+            match pair {
                 ("capability", val) => {
                     self.capability = val.split(",").map(|s| String::from(s)).collect()
                 }
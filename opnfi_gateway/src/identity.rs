@@ -0,0 +1,169 @@
+use crate::settings::IdentitySettings;
+use crate::sys::SysRoot;
+
+/// DMI serials left at a vendor placeholder, compared lowercase
+const PLACEHOLDER_SERIALS: &[&str] = &[
+    "to be filled by o.e.m.",
+    "default string",
+    "system serial number",
+    "not specified",
+    "not applicable",
+    "none",
+    "n/a",
+    "0",
+    "0123456789",
+];
+
+// ===== Identity =====
+
+/// What the host reports about itself, every field can be overridden in the settings.
+#[derive(PartialEq, Clone, Debug, Default)]
+pub(crate) struct UnixHostIdentity {
+    pub hostname: String,
+    pub board_vendor: Option<String>,
+    pub board_name: Option<String>,
+    /// BIOS version, reported as the bootrom version
+    pub bootrom_version: Option<String>,
+    /// DMI serial, or one derived from the machine id, None if the host has neither
+    pub serial: Option<String>,
+    /// OS release, falling back to the kernel release
+    pub os_version: Option<String>,
+    /// Firmware version from the settings, the profile's required version is reported when unset
+    pub version: Option<String>,
+}
+
+impl UnixHostIdentity {
+    pub fn load(root: &SysRoot, settings: &IdentitySettings) -> Self {
+        let dmi = |name: &str| {
+            root.read_to_string(format!("/sys/class/dmi/id/{}", name))
+                .ok()
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+        let hostname = root
+            .read_to_string("/proc/sys/kernel/hostname")
+            .or_else(|_| root.read_to_string("/etc/hostname"))
+            .map(|hostname| hostname.trim().to_string())
+            .unwrap_or_default();
+        let serial = dmi("product_serial")
+            .and_then(|serial| sanitize_serial(&serial))
+            .or_else(|| dmi("board_serial").and_then(|serial| sanitize_serial(&serial)))
+            .or_else(|| machine_serial(root));
+        let os_version = os_version(root).or_else(|| {
+            root.read_to_string("/proc/sys/kernel/osrelease")
+                .ok()
+                .map(|release| release.trim().to_string())
+                .filter(|release| !release.is_empty())
+        });
+
+        UnixHostIdentity {
+            hostname: settings.hostname.clone().unwrap_or(hostname),
+            board_vendor: settings
+                .board_vendor
+                .clone()
+                .or_else(|| dmi("board_vendor")),
+            board_name: settings.board_name.clone().or_else(|| dmi("board_name")),
+            bootrom_version: settings
+                .bootrom_version
+                .clone()
+                .or_else(|| dmi("bios_version")),
+            serial: settings.serial.clone().or(serial),
+            os_version,
+            version: settings.version.clone(),
+        }
+    }
+}
+
+/// Uppercase alphanumerics of a DMI serial, None for vendor placeholders
+fn sanitize_serial(serial: &str) -> Option<String> {
+    if PLACEHOLDER_SERIALS.contains(&serial.to_lowercase().as_str()) {
+        return None;
+    }
+    let serial: String = serial
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    if serial.is_empty() {
+        None
+    } else {
+        Some(serial)
+    }
+}
+
+/// First 12 hex digits of the systemd machine id, shaped like a MAC based serial
+fn machine_serial(root: &SysRoot) -> Option<String> {
+    let machine_id = root
+        .read_to_string("/etc/machine-id")
+        .or_else(|_| root.read_to_string("/var/lib/dbus/machine-id"))
+        .ok()?;
+    let machine_id = machine_id.trim();
+    if machine_id.len() < 12 || !machine_id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some(machine_id[..12].to_uppercase())
+}
+
+/// `VERSION_ID` from `/etc/os-release`
+fn os_version(root: &SysRoot) -> Option<String> {
+    root.read_to_string("/etc/os-release")
+        .or_else(|_| root.read_to_string("/usr/lib/os-release"))
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("VERSION_ID="))
+        .map(|value| value.trim_matches('"').to_string())
+        .filter(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sys::fixture;
+
+    #[test]
+    fn test_load() {
+        let identity = UnixHostIdentity::load(&fixture("basic"), &IdentitySettings::default());
+        assert_eq!(identity.hostname, "gw01");
+        assert_eq!(identity.board_vendor.as_deref(), Some("Supermicro"));
+        assert_eq!(identity.board_name.as_deref(), Some("A2SDi-4C-HLN4F"));
+        assert_eq!(identity.bootrom_version.as_deref(), Some("1.1c"));
+        assert_eq!(identity.serial.as_deref(), Some("S123456X9"));
+        assert_eq!(identity.os_version.as_deref(), Some("11"));
+        assert_eq!(identity.version, None);
+    }
+
+    #[test]
+    fn test_load_without_dmi() {
+        let identity = UnixHostIdentity::load(&fixture("bridged"), &IdentitySettings::default());
+        assert_eq!(identity.hostname, "router");
+        assert_eq!(identity.board_name, None);
+        assert_eq!(identity.serial.as_deref(), Some("0B2F4E6C8A1D"));
+        assert_eq!(identity.os_version.as_deref(), Some("4.19.0-18-amd64"));
+    }
+
+    #[test]
+    fn test_overrides() {
+        let settings = IdentitySettings {
+            hostname: Some("edge".to_string()),
+            serial: Some("FCECDA000001".to_string()),
+            version: Some("4.4.44".to_string()),
+            ..IdentitySettings::default()
+        };
+        let identity = UnixHostIdentity::load(&fixture("basic"), &settings);
+        assert_eq!(identity.hostname, "edge");
+        assert_eq!(identity.serial.as_deref(), Some("FCECDA000001"));
+        assert_eq!(identity.version.as_deref(), Some("4.4.44"));
+        assert_eq!(identity.os_version.as_deref(), Some("11"));
+        assert_eq!(identity.bootrom_version.as_deref(), Some("1.1c"));
+    }
+
+    #[test]
+    fn test_sanitize_serial() {
+        assert_eq!(sanitize_serial("Default string"), None);
+        assert_eq!(sanitize_serial("--"), None);
+        assert_eq!(
+            sanitize_serial("abc-123 456"),
+            Some("ABC123456".to_string())
+        );
+    }
+}
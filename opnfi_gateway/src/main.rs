@@ -7,18 +7,40 @@ extern crate lib_opnfi;
 extern crate regex;
 extern crate simple_logger;
 
+use crate::command::{CmdRegistry, LocateHandler};
 use crate::config::Config;
+use crate::identity::UnixHostIdentity;
+use crate::net::clients::UnixClientTable;
+use crate::net::conntrack::{UnixConntrackAccounting, UnixConntrackTable};
+use crate::net::device::{UnixNetworkDevice, UnixNetworkInterface};
+use crate::net::dpi::DpiStats;
 use crate::net::nameservers::get_nameservers;
+use crate::net::ports::{detect_ports, map_ports, port_table, unplaced_ports, Port, PortRole};
+use crate::net::routes::UnixRoutingTable;
+use crate::net::wan::{delegated_prefixes, detect_wan_config, WanFailover};
+use crate::service::dnsmasq::Dnsmasq;
+use crate::service::monitor::{WanMonitor, WanProbeSource};
+use crate::service::speedtest::SpeedTest;
+use crate::settings::Settings;
+use crate::stats::UnixSystemStats;
+use crate::sys::SysRoot;
 use crate::util::*;
-use lib_opnfi::inform::payload::gateway::OpnFiInformGatewayPayload;
+use enumflags2::BitFlags;
+use lib_opnfi::error::OpnFiError;
+use lib_opnfi::inform::client::OpnFiInformClient;
+use lib_opnfi::inform::payload::command::OpnFiRebootType;
+use lib_opnfi::inform::payload::gateway::{OpnFiFirewallCap, OpnFiInformGatewayPayload};
 use lib_opnfi::inform::payload::net::{
-    OpnFiInformConfigPortTableItem, OpnFiInformNetworkConfig, OpnFiInformNetworkInterface,
+    OpnFiInformHost, OpnFiInformNetworkConfig, OpnFiInformNetworkInterface,
+    OpnFiInformNetworkTableEntry,
 };
-use lib_opnfi::inform::payload::stats::OpnFiInformSystemStatus;
-use lib_opnfi::inform::payload::{command::OpnFiInformPayloadCommand, OpnFiInformPayload};
-use lib_opnfi::inform::{OpnFiReadExt, OpnFiWriteExt, OpnfiInformPacket, OpnfiInformPacketFlag};
+use lib_opnfi::inform::payload::stats::OpnFiInformDiskUsage;
+use lib_opnfi::inform::payload::OpnFiInformPayload;
+use lib_opnfi::inform::protocol::OpnFiDeviceAction;
+use lib_opnfi::model::OpnFiModelProfile;
 use pnet::util::MacAddr;
-use rand::prelude::*;
+use std::collections::HashSet;
+use std::net::IpAddr;
 use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
@@ -28,10 +50,16 @@ use std::{
     thread::sleep,
     time::{Duration, Instant},
 };
-use sysinfo::{ProcessorExt, SystemExt};
+use sysinfo::{DiskExt, SystemExt};
 
+mod command;
 mod config;
+mod identity;
 mod net;
+mod service;
+mod settings;
+mod stats;
+mod sys;
 mod util;
 
 type Result = std::result::Result<(), Box<dyn error::Error + 'static>>;
@@ -53,6 +81,22 @@ fn main() -> Result {
                 .help("Sets a config file path to use")
                 .takes_value(true),
         )
+        .arg(
+            clap::Arg::with_name("settings")
+                .short("s")
+                .long("settings")
+                .value_name("FILE")
+                .help("Sets a device settings file path to use")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("root")
+                .long("root")
+                .value_name("DIR")
+                .help("Sets the root sysfs, procfs and /etc are read from")
+                .takes_value(true)
+                .default_value("/"),
+        )
         .arg(
             clap::Arg::with_name("controller")
                 .long("controller")
@@ -60,23 +104,31 @@ fn main() -> Result {
                 .help("Sets a config file path to use")
                 .takes_value(true),
         )
+        .arg(
+            clap::Arg::with_name("model")
+                .short("m")
+                .long("model")
+                .value_name("MODEL")
+                .help("Sets the emulated model, e.g. UGW3, UGW4, UGWXG or UXGPRO")
+                .takes_value(true),
+        )
         .arg(
             clap::Arg::with_name("wan")
                 .short("w")
                 .long("wan")
                 .value_name("NIC")
-                .help("Set the nic to report for WAN")
-                .takes_value(true)
-                .default_value("eth0"),
+                .help("Set the nic to report for WAN, defaults to the default route interface")
+                .takes_value(true),
         )
         .arg(
             clap::Arg::with_name("lan")
                 .short("l")
                 .long("lan")
                 .value_name("NIC")
-                .help("Set the nic to report for LAN")
+                .help("Set the comma separated nics to report for LAN, defaults to all other nics")
                 .takes_value(true)
-                .default_value("eth1"),
+                .multiple(true)
+                .use_delimiter(true),
         )
         .get_matches();
 
@@ -90,6 +142,34 @@ fn main() -> Result {
         info!("Unable to locate existing config, entering adoption mode.");
     }
 
+    let settings_path = path::Path::new(
+        matches
+            .value_of("settings")
+            .unwrap_or("./config/opnfi_device.toml"),
+    );
+    let settings = Settings::load(settings_path)?;
+    let mut dnsmasq = if settings.dnsmasq.enabled {
+        info!(
+            "Managing dnsmasq config at {}",
+            settings.dnsmasq.config_path.display()
+        );
+        Some(Dnsmasq::new(settings.dnsmasq.clone()))
+    } else {
+        None
+    };
+
+    let mut reboot_executor = command::reboot::executor(&settings.reboot);
+    let mut pending_reboot: Option<OpnFiRebootType> = None;
+    let locating = Arc::new(AtomicBool::new(false));
+    let mut cmd_registry = CmdRegistry::new();
+    cmd_registry.register("set-locate", LocateHandler::new(locating.clone()));
+    cmd_registry.register("unset-locate", LocateHandler::new(locating.clone()));
+    let speedtest = SpeedTest::new(settings.speedtest.clone());
+    if settings.speedtest.is_configured() {
+        cmd_registry.register("speed-test", speedtest.clone());
+        cmd_registry.register("speed-test-status", speedtest.clone());
+    }
+
     let inform_url = match matches.value_of("controller") {
         Some(host) => format!("http://{}:8080/inform", host),
         None => match &config {
@@ -99,30 +179,81 @@ fn main() -> Result {
     };
     info!("Reporting inform packets to {}", inform_url);
 
-    info!("Reporting inform packets to {}", inform_url);
-    let mut wan_device = match matches.value_of("wan") {
-        Some(wan_name) => {
-            info!("Using {} as WAN device.", wan_name);
-            net::device::UnixNetworkDevice::new(&wan_name.to_string()).ok()
+    let model = matches
+        .value_of("model")
+        .or_else(|| settings.model.as_deref())
+        .unwrap_or(OpnFiModelProfile::DEFAULT_MODEL);
+    let profile = match OpnFiModelProfile::find(model) {
+        Some(profile) => profile,
+        None => {
+            let models: Vec<&str> = OpnFiModelProfile::ALL.iter().map(|p| p.model).collect();
+            return Err(Box::new(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Unknown model {}, expected one of {}",
+                    model,
+                    models.join(", ")
+                ),
+            )));
         }
-        None => None,
     };
-    let mut lan_device = match matches.value_of("lan") {
-        Some(lan_name) => {
-            info!("Using {} as LAN device.", lan_name);
-            net::device::UnixNetworkDevice::new(&lan_name.to_string()).ok()
-        }
-        None => None,
+    info!("Emulating {} ({})", profile.display_name, profile.model);
+    // Only advertise what the model has and this host implements
+    let fw_caps = profile.fw_cap_flags() & implemented_fw_caps(&settings);
+    debug!("Firewall capabilities: {:?}", fw_caps);
+
+    let root = SysRoot::new(matches.value_of("root").unwrap_or("/"));
+    let identity = UnixHostIdentity::load(&root, &settings.identity);
+    info!(
+        "Host {} on {} {}",
+        identity.hostname,
+        identity.board_vendor.as_deref().unwrap_or("unknown"),
+        identity.board_name.as_deref().unwrap_or("board")
+    );
+    let wan_name = matches.value_of("wan");
+    let lan_names: Vec<String> = matches
+        .values_of("lan")
+        .map(|lans| lans.map(String::from).collect())
+        .unwrap_or_default();
+    let mut devices = UnixNetworkDevice::list_devices(&root, &UnixNetworkInterface::list())?;
+    let mut routing_table = UnixRoutingTable::load(&root).unwrap_or_default();
+    // A configured port map wins over detecting ports from the default route
+    let select_ports = |devices: &[UnixNetworkDevice], uplink: Option<String>| {
+        let ports = if settings.ports.is_empty() {
+            detect_ports(devices, uplink.as_deref(), wan_name, &lan_names)
+        } else {
+            map_ports(devices, &settings.ports)
+        };
+        log_ports(profile, &ports);
+        ports
     };
+    let mut ports = select_ports(&devices, routing_table.uplink());
+    let wan_monitor = if settings.monitor.enabled {
+        let monitor = WanMonitor::new(settings.monitor.clone());
+        monitor.set_sources(WanProbeSource::for_ports(&ports, &devices));
+        monitor.start();
+        Some(monitor)
+    } else {
+        None
+    };
+    let wan_reachability = || {
+        wan_monitor
+            .as_ref()
+            .map(WanMonitor::reachability)
+            .unwrap_or_default()
+    };
+    let mut wan_failover = WanFailover::new(settings.wan.mode);
+    wan_failover.update(&ports, &devices, &routing_table, &wan_reachability());
 
     let mut sysinf = sysinfo::System::new();
-    let http_client = reqwest::Client::new();
-    let mut rng = rand::rngs::StdRng::from_entropy();
-    let mut infom_interval = 10;
-    let mut last_inform = Instant::now()
-        .checked_sub(Duration::from_secs(infom_interval))
-        .unwrap();
-    let mut send_inform = true;
+    let mut system_stats = UnixSystemStats::new(&root);
+    let mut conntrack_accounting = UnixConntrackAccounting::default();
+    let mut dpi = DpiStats::default();
+    let mut inform_client = OpnFiInformClient::new(&inform_url, MacAddr::zero());
+    if let Some(config) = &config {
+        use_config(&mut inform_client, config);
+    }
+    let mut last_route_check = Instant::now();
     let running = Arc::new(AtomicBool::new(true));
     let loop_running = running.clone();
     ctrlc::set_handler(move || {
@@ -132,131 +263,240 @@ fn main() -> Result {
 
     while running.load(Ordering::SeqCst) {
         let now = Instant::now();
-        if now.duration_since(last_inform).as_secs() >= infom_interval {
-            send_inform = true;
-            sysinf.refresh_all();
-            if let Some(wan) = &mut wan_device {
-                wan.refresh()?;
+        if now.duration_since(last_route_check).as_secs() >= 1 {
+            last_route_check = now;
+            let table = UnixRoutingTable::load(&root).unwrap_or_default();
+            if table.default_route() != routing_table.default_route() {
+                info!("Default route moved to {:?}", table.uplink());
+                ports = select_ports(&devices, table.uplink());
+                inform_client.inform_now();
             }
-            if let Some(lan) = &mut lan_device {
-                lan.refresh()?;
+            routing_table = table;
+            if wan_failover.update(&ports, &devices, &routing_table, &wan_reachability()) {
+                info!("Active WAN is now {:?}", wan_failover.active());
+                inform_client.inform_now();
             }
         }
+        if inform_client.poll_inform(now) {
+            sysinf.refresh_all();
+            let interfaces = UnixNetworkInterface::list();
+            match UnixNetworkDevice::refresh_devices(&root, &interfaces, &mut devices) {
+                // Pick up hot-plugged and removed NICs
+                Ok(true) => ports = select_ports(&devices, routing_table.uplink()),
+                Ok(false) => {}
+                Err(e) => warn!("Refresh devices: Err -> {}", e),
+            }
+            // Follow WAN changes and address renewals
+            if let Some(monitor) = &wan_monitor {
+                monitor.set_sources(WanProbeSource::for_ports(&ports, &devices));
+            }
 
-        if send_inform {
-            send_inform = false;
-            last_inform = now;
+            // A requested reboot waits for one more inform so the controller sees it
+            let reboot_after_inform = pending_reboot.take();
 
             // Load SysInfo for inform
             let uptime = sysinf.get_uptime() as u64;
-            let mem_used = sysinf.get_used_memory() as f32;
-            let mem_total = sysinf.get_total_memory() as f32;
-            let mem_usage = (mem_used / mem_total * 100f32) as u64;
-            let procs = sysinf.get_processor_list();
-            let cpu_usage = (procs[0].get_cpu_usage() * 100f32) as u64;
+            let mut system_status = system_stats.collect();
+            system_status.disks = sysinf
+                .get_disks()
+                .iter()
+                .map(|disk| OpnFiInformDiskUsage {
+                    mount: disk.get_mount_point().display().to_string(),
+                    total: disk.get_total_space(),
+                    used: disk
+                        .get_total_space()
+                        .saturating_sub(disk.get_available_space()),
+                })
+                .collect();
 
-            // Load key
-            let key = match &config {
-                Some(config) => hex::decode(config.authkey.as_str())
-                    .map(|v| copy_to_array(v.as_slice()))
-                    .ok(),
-                _ => None,
-            };
-
-            // Packet flags
-            let mut flags = OpnfiInformPacketFlag::Encrypted as u16
-                | OpnfiInformPacketFlag::ZLibCompressed as u16;
-            if let Some(config) = &config {
-                if config.use_aes_gcm {
-                    flags |= OpnfiInformPacketFlag::EncryptedGCM as u16;
+            // Interfaces
+            let port_device = |port: &Port| devices.iter().find(|d| d.name() == port.ifname);
+            let wan_port = ports.iter().find(|p| p.role == PortRole::Wan);
+            let speedtest_state = speedtest.state();
+            let mut if_table: Vec<OpnFiInformNetworkInterface> = Vec::new();
+            for port in ports.iter() {
+                let device = match port_device(port) {
+                    Some(device) => device,
+                    None => continue,
+                };
+                let mut interface: OpnFiInformNetworkInterface = device.clone().into();
+                interface.gateways = routing_table
+                    .gateways(&port.ifname)
+                    .iter()
+                    .map(|gw| gw.to_string())
+                    .collect();
+                if Some(port) == wan_port {
+                    speedtest_state.apply(&mut interface);
                 }
+                if port.role == PortRole::Wan {
+                    if let Some(stats) = wan_monitor
+                        .as_ref()
+                        .and_then(|monitor| monitor.stats(&port.ifname))
+                    {
+                        stats.apply(&mut interface);
+                    }
+                    interface.ipv6_pd = delegated_prefixes(&root, &port.ifname);
+                    for ns in get_nameservers(&root)?.iter() {
+                        interface.nameservers.push(ns.to_string());
+                    }
+                }
+                if_table.push(interface);
             }
-
-            // Initialization Vector for crypto
-            let mut initialization_vector = [0u8; 16];
-            rng.fill_bytes(&mut initialization_vector);
-
-            // Interfaces
-            let wan_interface: Option<OpnFiInformNetworkInterface> = match &mut wan_device.as_ref()
-            {
-                Some(wan) => Some(wan.clone().into()),
-                _ => None,
+            // Clients on the LAN networks
+            let now_unix = unix_time();
+            let clients = UnixClientTable::load(&root, &settings.clients, now_unix);
+            let lan_clients: HashSet<IpAddr> = ports
+                .iter()
+                .filter(|port| port.role == PortRole::Lan)
+                .filter_map(port_device)
+                .flat_map(|device| {
+                    let interface = device.interface();
+                    clients.on_network(&interface.name, &interface.ips)
+                })
+                .filter_map(|client| client.ip)
+                .collect();
+            let conntrack = UnixConntrackTable::load(&root).unwrap_or_default();
+            conntrack_accounting.update(&conntrack, |ip| lan_clients.contains(ip));
+            let network_table: Vec<OpnFiInformNetworkTableEntry> = ports
+                .iter()
+                .filter(|port| port.role == PortRole::Lan)
+                .filter_map(port_device)
+                .map(|device| {
+                    let interface = device.interface();
+                    let host_table: Vec<OpnFiInformHost> = clients
+                        .on_network(&interface.name, &interface.ips)
+                        .iter()
+                        .map(|client| {
+                            let mut host = client.to_host(now_unix);
+                            if let Some(ip) = client.ip {
+                                conntrack_accounting.get(&ip).apply(&mut host);
+                            }
+                            host
+                        })
+                        .collect();
+                    OpnFiInformNetworkTableEntry {
+                        address: interface
+                            .ips
+                            .iter()
+                            .find(|ip| ip.ip.is_ipv4())
+                            .map(|ip| ip.to_string())
+                            .unwrap_or_default(),
+                        addresses: interface.ips.iter().map(|ip| ip.to_string()).collect(),
+                        num_sta: host_table.len(),
+                        host_table,
+                        mac: device.mac().to_string(),
+                        name: interface.name.clone(),
+                        up: device.link().is_up(),
+                    }
+                })
+                .collect();
+            let dpi_stats = if settings.dpi.enabled {
+                match dpi.collect(&root, &settings.dpi, &conntrack, |ip| {
+                    lan_clients.contains(ip)
+                }) {
+                    Ok(()) => Some(&dpi),
+                    Err(e) => {
+                        warn!("Failed to collect DPI stats: {}", e);
+                        None
+                    }
+                }
+            } else {
+                None
             };
-            let lan_interface: Option<OpnFiInformNetworkInterface> = match &mut lan_device.as_ref()
+
+            // The kernel's default route, which is where traffic actually goes
+            let uplink = routing_table.uplink();
+            let nameservers = get_nameservers(&root).unwrap_or_default();
+            let wan_config = |index: usize| match ports
+                .iter()
+                .filter(|p| p.role == PortRole::Wan)
+                .nth(index)
+                .and_then(port_device)
             {
-                Some(lan) => Some(lan.clone().into()),
-                _ => None,
-            };
-            let mut if_table = Vec::new();
-            if let Some(wan) = &wan_interface {
-                let mut w = wan.clone();
-                for ns in get_nameservers()?.iter() {
-                    w.nameservers.push(ns.to_string());
+                Some(device) => {
+                    detect_wan_config(&root, &device.interface(), &routing_table, &nameservers)
                 }
-                w.name = String::from("eth0");
-                if_table.push(w);
-            }
-            if let Some(lan) = &lan_interface {
-                let mut l = lan.clone();
-                l.name = String::from("eth1");
-                if_table.push(l);
-            }
-
-            let (ip, netmask) = match &wan_interface {
-                Some(wan_if) => (wan_if.ip.clone(), wan_if.netmask.clone()),
-                None => (String::from("1.2.3.4"), String::from("255.255.255.0")),
+                None => OpnFiInformNetworkConfig::Disabled,
             };
-            let mac = match &wan_device {
-                Some(wan_if) => wan_if.mac(),
+
+            let (ip, netmask) =
+                match wan_port.and_then(|port| if_table.iter().find(|i| i.name == port.ifname)) {
+                    Some(wan_if) => (wan_if.ip.clone(), wan_if.netmask.clone()),
+                    None => (String::from("1.2.3.4"), String::from("255.255.255.0")),
+                };
+            let mac = match wan_port.or_else(|| ports.first()).and_then(port_device) {
+                Some(device) => device.mac(),
                 None => MacAddr::zero(),
             };
-            let serial = mac.to_string().replace(":", "");
+            // Only hosts without a DMI serial or machine id fall back to the MAC
+            let serial = identity
+                .serial
+                .clone()
+                .unwrap_or_else(|| mac.to_string().replace(":", ""));
 
             // Payload
             let payload = OpnFiInformPayload::Gateway(OpnFiInformGatewayPayload {
-                bootrom_version: "unknown".to_string(),
+                board_name: identity.board_name.clone(),
+                board_vendor: identity.board_vendor.clone(),
+                bootrom_version: identity
+                    .bootrom_version
+                    .clone()
+                    .unwrap_or_else(|| "unknown".to_string()),
                 cfgversion: match &config {
                     Some(config) => config.cfgversion.clone(),
                     _ => "0123456789abcdef".to_string(),
                 },
-                config_network_wan: OpnFiInformNetworkConfig::DHCP,
-                config_network_wan2: OpnFiInformNetworkConfig::default(),
-                config_port_table: vec![
-                    OpnFiInformConfigPortTableItem::new(
-                        "WAN".to_string(),
-                        wan_device.as_ref().unwrap().name(),
-                    ),
-                    OpnFiInformConfigPortTableItem::new(
-                        "LAN".to_string(),
-                        lan_device.as_ref().unwrap().name(),
-                    ),
-                ],
+                config_network_wan: wan_config(0),
+                config_network_wan2: wan_config(1),
+                config_port_table: port_table(profile, &ports),
                 default: config.is_none(),
                 discovery_response: false,
-                fw_caps: std::i32::MAX,
-                has_eth1: true,
-                has_ssh_disable: true,
-                hostname: "fake-dev.local".to_string(),
+                dpi_stats: dpi_stats
+                    .map(|stats| {
+                        stats.client_stats(|ip| clients.find_by_ip(ip).map(|client| client.mac))
+                    })
+                    .unwrap_or_default(),
+                dpi_stats_table: dpi_stats.map(DpiStats::table),
+                fw_caps,
+                has_default_route_distance: fw_caps
+                    .contains(OpnFiFirewallCap::DefaultRouteDistance),
+                has_dnsmasq_hostfile_update: fw_caps
+                    .contains(OpnFiFirewallCap::DnsmasqHostfileUpdate),
+                has_dpi: fw_caps.contains(OpnFiFirewallCap::Dpi),
+                has_eth1: ports.iter().any(|p| p.role == PortRole::Lan),
+                has_porta: fw_caps.contains(OpnFiFirewallCap::Porta),
+                has_ssh_disable: fw_caps.contains(OpnFiFirewallCap::SshDisable),
+                has_vti: fw_caps.contains(OpnFiFirewallCap::Vti),
+                hostname: identity.hostname.clone(),
                 inform_url: inform_url.clone(),
                 if_table,
                 ip,
+                locating: locating.load(Ordering::SeqCst),
                 mac: mac.to_string(),
-                model: "UGWXG".to_string(),
-                model_display: "UniFi Security Gateway XG-8".to_string(),
+                model: profile.model.to_string(),
+                model_display: profile.display_name.to_string(),
                 netmask,
-                radius_caps: 0,
-                required_version: "0.0.1".to_string(),
+                network_table,
+                os_version: identity.os_version.clone(),
+                // No RADIUS server runs on the host
+                radius_caps: BitFlags::empty(),
+                required_version: profile.required_version.to_string(),
                 selfrun_beacon: true,
                 serial,
-                state: 1,
-                system_status: OpnFiInformSystemStatus::new(
-                    cpu_usage.to_string(),
-                    mem_usage.to_string(),
-                ),
+                speedtest_status: Some((&speedtest_state).into()),
+                state: inform_client.state(),
+                sys_stats: system_stats.collect_sys(),
+                system_status,
                 time: uptime as usize,
-                uplink: "eth0".to_string(),
+                uplink: uplink.unwrap_or_default(),
                 uptime: uptime as usize,
-                version: "2.4.4-RELEASE-p3".to_string(),
+                // The controller compares it against the model's firmware
+                version: identity
+                    .version
+                    .clone()
+                    .unwrap_or_else(|| profile.required_version.to_string()),
+                wan_mode: wan_failover.mode(),
+                wan_status: wan_failover.status(),
                 ..OpnFiInformGatewayPayload::default()
             });
             if true {
@@ -265,90 +505,79 @@ fn main() -> Result {
                     Err(e) => warn!("{}", e),
                 }
             }
-            let inform_packet =
-                lib_opnfi::inform::OpnfiInformPacket::new(None, 0, mac, flags, 1, payload);
-
-            // Write Inform packet to a buffer
-            let mut inform_data = Vec::new();
-            inform_packet.write::<byteorder::NetworkEndian>(
-                key,
-                initialization_vector,
-                &mut inform_data,
-            )?;
-
-            let inform_response = http_client
-                .post(inform_url.as_str())
-                .body(inform_data)
-                .send();
-
-            if let Ok(mut inform_response) = inform_response {
-                info!("Sent inform packet");
-                if inform_response.status().is_success() {
-                    info!("Parsing inform packet response...");
-                    if let Some(body_length) = inform_response.content_length() {
-                        if body_length >= 40 {
-                            let mut inform_response_body = Vec::new();
-                            inform_response.copy_to(&mut inform_response_body)?;
-                            let mut inform_response_body = io::Cursor::new(inform_response_body);
-                            let packet: lib_opnfi::Result<OpnfiInformPacket<OpnFiInformPayload>> =
-                                OpnfiInformPacket::read::<byteorder::NetworkEndian>(
-                                    key,
-                                    None,
-                                    &mut inform_response_body,
-                                );
-                            if let Ok(packet) = packet {
-                                match packet.payload {
-                                    OpnFiInformPayload::Command(cmd) => match cmd {
-                                        OpnFiInformPayloadCommand::NoOp(noop) => {
-                                            infom_interval = noop.interval().as_secs()
-                                        }
-                                        OpnFiInformPayloadCommand::SetParam(params) => {
-                                            if let Some(mgmt_cfg) = params.mgmt_cfg {
-                                                match &mut config {
-                                                    Some(config) => {
-                                                        config.update_from_mgmt_cfg(mgmt_cfg)
-                                                    }
-                                                    None => {
-                                                        config =
-                                                            Some(Config::from_mgmt_cfg(mgmt_cfg))
-                                                    }
-                                                }
-                                                if let Some(config) = &config {
-                                                    match config.save(&config_path) {
-                                                        Ok(_) => info!("Config save: OK"),
-                                                        Err(e) => {
-                                                            error!("Config save: Err -> {}", e)
-                                                        }
-                                                    }
-                                                }
-                                                send_inform = true;
-                                            }
-                                        }
-                                        OpnFiInformPayloadCommand::SetDefault(_) => {
-                                            fs::remove_file(config_path)?;
-                                            config = None;
-                                            send_inform = true;
+            inform_client.protocol().set_hardware_address(mac);
+            match inform_client.send(payload) {
+                Ok(actions) => {
+                    info!("Sent inform packet");
+                    for action in actions {
+                        match action {
+                            OpnFiDeviceAction::StateChanged(state) => {
+                                info!("Device state: {:?}", state)
+                            }
+                            OpnFiDeviceAction::ApplyConfig(params) => {
+                                if let (Some(dnsmasq), Some(system_cfg)) =
+                                    (&mut dnsmasq, params.parse_system_cfg())
+                                {
+                                    match system_cfg {
+                                        Ok(system_cfg) => match dnsmasq.apply(&system_cfg) {
+                                            Ok(true) => info!("dnsmasq config: Updated"),
+                                            Ok(false) => info!("dnsmasq config: Unchanged"),
+                                            Err(e) => error!("dnsmasq config: Err -> {}", e),
+                                        },
+                                        Err(e) => error!("system_cfg: Err -> {}", e),
+                                    }
+                                }
+                                if let Some(mgmt_cfg) = params.mgmt_cfg {
+                                    match &mut config {
+                                        Some(config) => config.update_from_mgmt_cfg(mgmt_cfg),
+                                        None => config = Some(Config::from_mgmt_cfg(mgmt_cfg)),
+                                    }
+                                    if let Some(config) = &config {
+                                        match config.save(&config_path) {
+                                            Ok(_) => info!("Config save: OK"),
+                                            Err(e) => error!("Config save: Err -> {}", e),
                                         }
-                                        cmd => warn!("Unhandled Command: {:?}", cmd),
-                                    },
-                                    payload => warn!("Unhandled: {:?}", payload),
+                                    }
+                                }
+                            }
+                            OpnFiDeviceAction::SetDefault => {
+                                fs::remove_file(config_path)?;
+                                config = None;
+                            }
+                            OpnFiDeviceAction::Reboot(reboot_type) => {
+                                info!("Reboot requested: {:?}", reboot_type);
+                                pending_reboot = Some(reboot_type);
+                            }
+                            OpnFiDeviceAction::Cmd(cmd) => {
+                                match cmd_registry.dispatch(&cmd.command()) {
+                                    Ok(_) => info!("Cmd {}: OK", cmd.cmd),
+                                    Err(e) => {
+                                        error!("Cmd {} ({}): Err -> {}", cmd.cmd, cmd.id, e)
+                                    }
                                 }
                             }
+                            OpnFiDeviceAction::Unhandled(command) => {
+                                warn!("Unsupported command {}: {:?}", command.name(), command)
+                            }
+                            action => warn!("Unexpected: {:?}", action),
                         }
                     }
-                } else if config.is_none() {
-                    info!("Device is pending adoption");
-                    infom_interval = 10;
-                } else {
-                    warn!(
-                        "Controller response status code: {}",
-                        inform_response.status()
-                    );
-                    infom_interval = 10;
                 }
-            } else {
-                warn!("Unable to send inform packet.");
-                infom_interval = 10;
+                Err(OpnFiError::HttpError(e)) => warn!("Unable to send inform packet: {}", e),
+                Err(OpnFiError::HttpStatus(status)) => {
+                    if inform_client.is_adopted() {
+                        warn!("Controller response status code: {}", status);
+                    } else {
+                        info!("Device is pending adoption");
+                    }
+                }
+                Err(e) => warn!("Unable to read inform response: {}", e),
+            }
+
+            if let Some(reboot_type) = reboot_after_inform {
+                if let Err(e) = reboot_executor.reboot(reboot_type) {
+                    error!("Reboot: Err -> {}", e);
+                }
             }
         }
 
@@ -359,3 +588,43 @@ fn main() -> Result {
 
     Ok(())
 }
+
+/// Resume the session of an adopted device from its saved config
+fn use_config(inform_client: &mut OpnFiInformClient, config: &Config) {
+    let protocol = inform_client.protocol();
+    if let Err(e) = protocol.set_authkey(Some(&config.authkey)) {
+        error!("Config authkey: Err -> {}", e);
+    }
+    protocol.set_use_aes_gcm(config.use_aes_gcm);
+    protocol.set_cfgversion(Some(&config.cfgversion));
+}
+
+/// Firewall features opnfi_device implements with the current settings
+fn implemented_fw_caps(settings: &Settings) -> BitFlags<OpnFiFirewallCap> {
+    let mut caps = BitFlags::empty();
+    if settings.speedtest.is_configured() {
+        caps |= OpnFiFirewallCap::SpeedTest;
+    }
+    if settings.dpi.enabled {
+        caps |= OpnFiFirewallCap::Dpi;
+    }
+    if settings.dnsmasq.enabled {
+        caps |= OpnFiFirewallCap::DnsmasqHostfileUpdate;
+    }
+    caps
+}
+
+fn log_ports(profile: &OpnFiModelProfile, ports: &[Port]) {
+    if ports.iter().all(|p| p.role != PortRole::Wan) {
+        warn!("No WAN interface detected");
+    }
+    for port in ports {
+        info!("Using {} as {} device.", port.ifname, port.name);
+    }
+    for port in unplaced_ports(profile, ports) {
+        warn!(
+            "{} has no {} port, {} is missing from its port table",
+            profile.model, port.name, port.ifname
+        );
+    }
+}
@@ -0,0 +1,372 @@
+use crate::net::device::UnixNetworkAddress;
+use crate::settings::ClientSettings;
+use crate::sys::SysRoot;
+use crate::util::unix_time_from_utc;
+use lib_opnfi::inform::payload::net::OpnFiInformHost;
+use pnet::util::MacAddr;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+const ATF_COM: u32 = 0x2;
+
+/// Parses a client source given the current unix time
+type ClientParser = fn(&str, u64) -> Vec<UnixClient>;
+
+// ===== Client =====
+
+/// A client known from ARP or a DHCP lease.
+#[derive(PartialEq, Clone, Debug)]
+pub(crate) struct UnixClient {
+    pub mac: MacAddr,
+    pub ip: Option<IpAddr>,
+    pub hostname: Option<String>,
+    /// Interface the client was seen on
+    pub interface: Option<String>,
+    /// Unix time the client was last seen
+    pub last_seen: Option<u64>,
+}
+
+impl UnixClient {
+    pub fn new(mac: MacAddr) -> Self {
+        UnixClient {
+            mac,
+            ip: None,
+            hostname: None,
+            interface: None,
+            last_seen: None,
+        }
+    }
+
+    /// Merge a newer record of the same client, keeping what it does not know.
+    pub fn merge(&mut self, other: UnixClient) {
+        self.ip = other.ip.or(self.ip);
+        self.hostname = other.hostname.or_else(|| self.hostname.take());
+        self.interface = other.interface.or_else(|| self.interface.take());
+        self.last_seen = self.last_seen.max(other.last_seen);
+    }
+
+    pub fn to_host(&self, now: u64) -> OpnFiInformHost {
+        OpnFiInformHost {
+            age: self
+                .last_seen
+                .map(|last_seen| now.saturating_sub(last_seen) as usize)
+                .unwrap_or_default(),
+            authorized: true,
+            hostname: self.hostname.clone(),
+            ip: self.ip.map(|ip| ip.to_string()).unwrap_or_default(),
+            mac: self.mac.to_string(),
+            ..OpnFiInformHost::default()
+        }
+    }
+}
+
+// ===== Client Table =====
+
+/// Clients merged by MAC from `/proc/net/arp` and the DHCP lease files.
+#[derive(PartialEq, Clone, Debug, Default)]
+pub(crate) struct UnixClientTable {
+    clients: BTreeMap<String, UnixClient>,
+}
+
+impl UnixClientTable {
+    /// Load every source, leases first so the ARP table has the final say on addresses.
+    pub fn load(root: &SysRoot, settings: &ClientSettings, now: u64) -> Self {
+        let mut table = UnixClientTable::default();
+        let sources: Vec<(&std::path::Path, ClientParser)> = vec![
+            (settings.dnsmasq_leases.as_path(), parse_dnsmasq_leases),
+            (settings.dhcpd_leases.as_path(), parse_dhcpd_leases),
+            (settings.kea_leases.as_path(), parse_kea_leases),
+            (std::path::Path::new("/proc/net/arp"), parse_arp),
+        ];
+        for (path, parse) in sources {
+            if let Ok(data) = root.read_to_string(path) {
+                for client in parse(&data, now) {
+                    table.insert(client);
+                }
+            }
+        }
+        table
+    }
+
+    pub fn insert(&mut self, client: UnixClient) {
+        match self.clients.get_mut(&client.mac.to_string()) {
+            Some(existing) => existing.merge(client),
+            None => {
+                self.clients.insert(client.mac.to_string(), client);
+            }
+        }
+    }
+
+    pub fn find_by_ip(&self, ip: &IpAddr) -> Option<&UnixClient> {
+        self.clients
+            .values()
+            .find(|client| client.ip.as_ref() == Some(ip))
+    }
+
+    /// Clients seen on an interface, or with an address in one of its subnets
+    pub fn on_network(&self, ifname: &str, networks: &[UnixNetworkAddress]) -> Vec<&UnixClient> {
+        self.clients
+            .values()
+            .filter(|client| match (&client.interface, &client.ip) {
+                (Some(interface), _) => interface == ifname,
+                (None, Some(ip)) => networks.iter().any(|network| network.contains(ip)),
+                (None, None) => false,
+            })
+            .collect()
+    }
+}
+
+// ===== Parsing =====
+
+fn parse_mac(value: &str) -> Option<MacAddr> {
+    MacAddr::from_str(value)
+        .ok()
+        .filter(|mac| *mac != MacAddr::zero())
+}
+
+/// Parse `/proc/net/arp`, incomplete entries are skipped.
+pub(crate) fn parse_arp(data: &str, now: u64) -> Vec<UnixClient> {
+    data.lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 6 {
+                return None;
+            }
+            let flags = u32::from_str_radix(fields[2].trim_start_matches("0x"), 16).ok()?;
+            if flags & ATF_COM == 0 {
+                return None;
+            }
+            let mut client = UnixClient::new(parse_mac(fields[3])?);
+            client.ip = IpAddr::from_str(fields[0]).ok();
+            client.interface = Some(fields[5].to_string());
+            client.last_seen = Some(now);
+            Some(client)
+        })
+        .collect()
+}
+
+/// Parse a dnsmasq lease file: expiry, MAC, IP, hostname and client id per line.
+pub(crate) fn parse_dnsmasq_leases(data: &str, now: u64) -> Vec<UnixClient> {
+    data.lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 4 {
+                return None;
+            }
+            // An expiry of 0 is an infinite lease
+            let expiry: u64 = fields[0].parse().ok()?;
+            if expiry != 0 && expiry < now {
+                return None;
+            }
+            let mut client = UnixClient::new(parse_mac(fields[1])?);
+            client.ip = IpAddr::from_str(fields[2]).ok();
+            client.hostname = Some(fields[3].to_string()).filter(|name| name != "*");
+            Some(client)
+        })
+        .collect()
+}
+
+/// Parse an ISC dhcpd lease file, only active bindings that didn't end yet are kept.
+pub(crate) fn parse_dhcpd_leases(data: &str, now: u64) -> Vec<UnixClient> {
+    let parse_time = |fields: &[&str]| -> Option<u64> {
+        // e.g. `cltt 1 2019/11/18 14:00:00;`
+        let date: Vec<u32> = fields
+            .get(2)?
+            .split('/')
+            .filter_map(|v| v.parse().ok())
+            .collect();
+        let time: Vec<u32> = fields
+            .get(3)?
+            .trim_end_matches(';')
+            .split(':')
+            .filter_map(|v| v.parse().ok())
+            .collect();
+        match (date.as_slice(), time.as_slice()) {
+            ([year, month, day], [hour, min, sec]) => Some(unix_time_from_utc(
+                i64::from(*year),
+                *month,
+                *day,
+                *hour,
+                *min,
+                *sec,
+            )),
+            _ => None,
+        }
+    };
+
+    struct Lease {
+        ip: IpAddr,
+        active: bool,
+        mac: Option<MacAddr>,
+        cltt: Option<u64>,
+        /// None for `ends never`
+        ends: Option<u64>,
+        hostname: Option<String>,
+    }
+
+    let mut leases: BTreeMap<IpAddr, Lease> = BTreeMap::new();
+    let mut current: Option<Lease> = None;
+    for line in data.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let value = |v: &str| v.trim_end_matches(';').trim_matches('"').to_string();
+        match (fields.as_slice(), current.as_mut()) {
+            (["lease", ip, "{"], _) => {
+                current = IpAddr::from_str(ip).ok().map(|ip| Lease {
+                    ip,
+                    active: false,
+                    mac: None,
+                    cltt: None,
+                    ends: None,
+                    hostname: None,
+                });
+            }
+            (["}"], Some(_)) => {
+                if let Some(lease) = current.take() {
+                    // Later entries for the same address replace earlier ones
+                    leases.insert(lease.ip, lease);
+                }
+            }
+            (["binding", "state", state], Some(lease)) => lease.active = value(state) == "active",
+            (["hardware", "ethernet", mac], Some(lease)) => lease.mac = parse_mac(&value(mac)),
+            (["cltt", ..], Some(lease)) => lease.cltt = parse_time(&fields),
+            (["ends", ..], Some(lease)) => lease.ends = parse_time(&fields),
+            (["client-hostname", name], Some(lease)) => lease.hostname = Some(value(name)),
+            _ => {}
+        }
+    }
+    leases
+        .into_iter()
+        .filter(|(_, lease)| lease.active && !matches!(lease.ends, Some(ends) if ends < now))
+        .filter_map(|(_, lease)| {
+            let mut client = UnixClient::new(lease.mac?);
+            client.ip = Some(lease.ip);
+            client.hostname = lease.hostname;
+            client.last_seen = lease.cltt;
+            Some(client)
+        })
+        .collect()
+}
+
+/// Parse a Kea memfile CSV lease file, columns are located by the header.
+pub(crate) fn parse_kea_leases(data: &str, now: u64) -> Vec<UnixClient> {
+    let mut lines = data.lines();
+    let header: Vec<&str> = match lines.next() {
+        Some(header) => header.split(',').collect(),
+        None => return Vec::new(),
+    };
+    let column = |name: &str| header.iter().position(|c| *c == name);
+    let (address, hwaddr, lifetime, expire, hostname) = match (
+        column("address"),
+        column("hwaddr"),
+        column("valid_lifetime"),
+        column("expire"),
+        column("hostname"),
+    ) {
+        (Some(a), Some(h), Some(l), Some(e), Some(n)) => (a, h, l, e, n),
+        _ => return Vec::new(),
+    };
+    let mut clients: BTreeMap<String, UnixClient> = BTreeMap::new();
+    for line in lines {
+        let fields: Vec<&str> = line.split(',').collect();
+        let field = |i: usize| fields.get(i).copied().unwrap_or_default();
+        let expire: u64 = match field(expire).parse() {
+            Ok(expire) if expire >= now => expire,
+            _ => continue,
+        };
+        let mac = match parse_mac(field(hwaddr)) {
+            Some(mac) => mac,
+            None => continue,
+        };
+        let mut client = UnixClient::new(mac);
+        client.ip = IpAddr::from_str(field(address)).ok();
+        client.hostname =
+            Some(field(hostname).trim_end_matches('.').to_string()).filter(|name| !name.is_empty());
+        client.last_seen = field(lifetime)
+            .parse::<u64>()
+            .ok()
+            .map(|lifetime| expire.saturating_sub(lifetime));
+        // The memfile is append only, the last line for a lease wins
+        clients.insert(mac.to_string(), client);
+    }
+    clients.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sys::fixture;
+    use std::net::Ipv4Addr;
+
+    /// 2019-11-18T14:26:08Z
+    const NOW: u64 = 1_574_087_168;
+
+    fn mac(value: &str) -> MacAddr {
+        MacAddr::from_str(value).unwrap()
+    }
+
+    #[test]
+    fn test_load() {
+        let table = UnixClientTable::load(&fixture("basic"), &ClientSettings::default(), NOW);
+        let macs: Vec<String> = table.clients.values().map(|c| c.mac.to_string()).collect();
+        assert_eq!(
+            macs,
+            vec![
+                "00:2e:5f:00:00:01",
+                "aa:bb:cc:00:00:01",
+                "aa:bb:cc:00:00:02",
+                "aa:bb:cc:00:00:04",
+            ]
+        );
+
+        let lan = UnixNetworkAddress::new(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)), 24);
+        let clients = table.on_network("eth1", &[lan]);
+        assert_eq!(clients.len(), 3);
+        // dnsmasq lease merged with its ARP entry
+        assert_eq!(clients[0].hostname, Some("laptop".to_string()));
+        assert_eq!(clients[0].interface, Some("eth1".to_string()));
+        assert_eq!(clients[0].last_seen, Some(NOW));
+        assert_eq!(clients[1].hostname, Some("printer".to_string()));
+        // Only known from Kea, matched by subnet
+        assert_eq!(clients[2].hostname, Some("phone.lan".to_string()));
+        assert_eq!(clients[2].last_seen, Some(1_574_084_400));
+
+        let host = clients[2].to_host(NOW);
+        assert_eq!(host.age, 2768);
+        assert_eq!(host.ip, "192.168.1.103");
+    }
+
+    #[test]
+    fn test_parse_dhcpd_leases() {
+        let root = fixture("basic");
+        let clients = parse_dhcpd_leases(
+            &root.read_to_string("/var/lib/dhcp/dhcpd.leases").unwrap(),
+            NOW,
+        );
+        // 192.168.1.105 is still marked active but ended before now
+        assert_eq!(clients.len(), 1);
+        assert_eq!(clients[0].mac, mac("aa:bb:cc:00:00:02"));
+        assert_eq!(clients[0].last_seen, Some(1_574_085_600));
+
+        let clients = parse_dhcpd_leases(
+            "lease 192.168.1.106 {\n  ends never;\n  binding state active;\n  \
+             hardware ethernet aa:bb:cc:00:00:06;\n}\n",
+            NOW,
+        );
+        assert_eq!(clients.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_arp() {
+        let clients = parse_arp(
+            "IP address       HW type     Flags       HW address            Mask     Device\n\
+             10.0.0.2         0x1         0x2         aa:bb:cc:dd:ee:ff     *        br0\n\
+             10.0.0.3         0x1         0x0         00:00:00:00:00:00     *        br0\n",
+            NOW,
+        );
+        assert_eq!(clients.len(), 1);
+        assert_eq!(clients[0].mac, mac("aa:bb:cc:dd:ee:ff"));
+        assert_eq!(clients[0].interface, Some("br0".to_string()));
+    }
+}
@@ -0,0 +1,359 @@
+use crate::sys::SysRoot;
+use lib_opnfi::inform::payload::net::OpnFiInformHost;
+use std::collections::HashMap;
+use std::io;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+// ===== Counters =====
+
+/// Open connections and traffic of an address.
+/// A table only covers tracked connections, see [`UnixConntrackAccounting`] for totals
+/// that keep the traffic of expired connections.
+/// Bytes and packets stay zero unless `nf_conntrack_acct` is enabled.
+#[derive(PartialEq, Copy, Clone, Debug, Default)]
+pub(crate) struct UnixConntrackCounters {
+    pub connections: usize,
+    pub rx_bytes: usize,
+    pub rx_packets: usize,
+    pub tx_bytes: usize,
+    pub tx_packets: usize,
+}
+
+impl UnixConntrackCounters {
+    pub fn apply(&self, host: &mut OpnFiInformHost) {
+        host.num_connections = self.connections;
+        host.rx_bytes = self.rx_bytes;
+        host.rx_packets = self.rx_packets;
+        host.tx_bytes = self.tx_bytes;
+        host.tx_packets = self.tx_packets;
+    }
+}
+
+// ===== Conntrack Table =====
+
+/// Tracked connections with their counters aggregated per address.
+#[derive(PartialEq, Clone, Debug, Default)]
+pub(crate) struct UnixConntrackTable {
+    connections: Vec<UnixConnection>,
+    counters: HashMap<IpAddr, UnixConntrackCounters>,
+}
+
+impl UnixConntrackTable {
+    /// Load `/proc/net/nf_conntrack`, falling back to the older `/proc/net/ip_conntrack`.
+    pub fn load(root: &SysRoot) -> io::Result<Self> {
+        let data = root
+            .read_to_string("/proc/net/nf_conntrack")
+            .or_else(|_| root.read_to_string("/proc/net/ip_conntrack"))?;
+        Ok(Self::parse(&data))
+    }
+
+    /// Each connection is counted for both ends, as seen from that end.
+    /// The reply source is the real host behind a destination NAT, e.g. a port forward.
+    pub fn parse(data: &str) -> Self {
+        let mut table = UnixConntrackTable::default();
+        for connection in data.lines().filter_map(parse_connection) {
+            for (sent, received) in connection.ends() {
+                table.add(sent, received);
+            }
+            table.connections.push(connection);
+        }
+        table
+    }
+
+    pub fn connections(&self) -> &[UnixConnection] {
+        &self.connections
+    }
+
+    /// Count a connection for the source of `sent`
+    fn add(&mut self, sent: &UnixConnectionDirection, received: &UnixConnectionDirection) {
+        let counters = self.counters.entry(sent.src).or_default();
+        counters.connections += 1;
+        add_traffic(counters, sent, received);
+    }
+}
+
+fn add_traffic(
+    counters: &mut UnixConntrackCounters,
+    sent: &UnixConnectionDirection,
+    received: &UnixConnectionDirection,
+) {
+    counters.tx_bytes += sent.bytes;
+    counters.tx_packets += sent.packets;
+    counters.rx_bytes += received.bytes;
+    counters.rx_packets += received.packets;
+}
+
+// ===== Accounting =====
+
+/// Samples a client may go without open connections before its totals are dropped,
+/// an hour at the default inform interval
+const EXPIRE_AFTER_SAMPLES: usize = 360;
+
+/// Traffic totals per client address across conntrack samples.
+/// Connections missing from a sample expired, their last counters are kept so totals
+/// only grow. Only clients are accounted, remote and gateway addresses are left out.
+#[derive(PartialEq, Clone, Debug, Default)]
+pub(crate) struct UnixConntrackAccounting {
+    /// Connections of the last sample with a client on either end
+    live: HashMap<UnixConnectionKey, UnixConnection>,
+    /// Traffic of expired connections
+    expired: HashMap<IpAddr, UnixConntrackCounters>,
+    /// Samples since a client with expired traffic last had open connections
+    idle: HashMap<IpAddr, usize>,
+    totals: HashMap<IpAddr, UnixConntrackCounters>,
+}
+
+impl UnixConntrackAccounting {
+    pub fn update(&mut self, table: &UnixConntrackTable, is_client: impl Fn(&IpAddr) -> bool) {
+        let live: HashMap<UnixConnectionKey, UnixConnection> = table
+            .connections()
+            .iter()
+            .filter(|c| c.ends().iter().any(|(sent, _)| is_client(&sent.src)))
+            .map(|c| (c.key(), c.clone()))
+            .collect();
+        for (key, previous) in self.live.drain() {
+            let ended = match live.get(&key) {
+                Some(current) => current.restarted_since(&previous),
+                None => true,
+            };
+            if ended {
+                for (sent, received) in previous.ends().iter() {
+                    if is_client(&sent.src) {
+                        add_traffic(self.expired.entry(sent.src).or_default(), sent, received);
+                    }
+                }
+            }
+        }
+        let mut totals: HashMap<IpAddr, UnixConntrackCounters> = table
+            .counters
+            .iter()
+            .filter(|(ip, _)| is_client(ip))
+            .map(|(ip, counters)| (*ip, *counters))
+            .collect();
+        for ip in self.expired.keys() {
+            let idle = self.idle.entry(*ip).or_default();
+            *idle = if totals.contains_key(ip) {
+                0
+            } else {
+                *idle + 1
+            };
+        }
+        let idle = &self.idle;
+        self.expired
+            .retain(|ip, _| matches!(idle.get(ip), Some(idle) if *idle < EXPIRE_AFTER_SAMPLES));
+        let expired = &self.expired;
+        self.idle.retain(|ip, _| expired.contains_key(ip));
+        for (ip, counters) in self.expired.iter() {
+            let total = totals.entry(*ip).or_default();
+            total.rx_bytes += counters.rx_bytes;
+            total.rx_packets += counters.rx_packets;
+            total.tx_bytes += counters.tx_bytes;
+            total.tx_packets += counters.tx_packets;
+        }
+        self.totals = totals;
+        self.live = live;
+    }
+
+    /// Totals for a client, with the connections it has open in the last sample
+    pub fn get(&self, ip: &IpAddr) -> UnixConntrackCounters {
+        self.totals.get(ip).copied().unwrap_or_default()
+    }
+}
+
+// ===== Connection =====
+
+/// Protocol and original direction addresses and ports, unique while a connection is tracked
+pub(crate) type UnixConnectionKey = (String, IpAddr, IpAddr, Option<u16>, Option<u16>);
+
+/// A tracked connection, `original` is the direction of the first packet.
+#[derive(PartialEq, Clone, Debug)]
+pub(crate) struct UnixConnection {
+    /// Protocol name, e.g. tcp, udp or icmp
+    pub protocol: String,
+    pub original: UnixConnectionDirection,
+    pub reply: UnixConnectionDirection,
+}
+
+impl UnixConnection {
+    pub fn key(&self) -> UnixConnectionKey {
+        (
+            self.protocol.clone(),
+            self.original.src,
+            self.original.dst,
+            self.original.sport,
+            self.original.dport,
+        )
+    }
+
+    /// Both ends as sent and received direction
+    fn ends(&self) -> [(&UnixConnectionDirection, &UnixConnectionDirection); 2] {
+        [(&self.original, &self.reply), (&self.reply, &self.original)]
+    }
+
+    /// Counters went backwards, the tuple was reused by a new connection
+    fn restarted_since(&self, previous: &UnixConnection) -> bool {
+        self.original.bytes < previous.original.bytes
+            || self.original.packets < previous.original.packets
+            || self.reply.bytes < previous.reply.bytes
+            || self.reply.packets < previous.reply.packets
+    }
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub(crate) struct UnixConnectionDirection {
+    pub src: IpAddr,
+    pub dst: IpAddr,
+    pub sport: Option<u16>,
+    pub dport: Option<u16>,
+    pub packets: usize,
+    pub bytes: usize,
+}
+
+// ===== Parsing =====
+
+/// Parse a conntrack line, the second `src` starts the reply direction.
+fn parse_connection(line: &str) -> Option<UnixConnection> {
+    let mut fields = line.split_whitespace();
+    let protocol = fields.nth(2)?.to_string();
+    let mut directions: Vec<UnixConnectionDirection> = Vec::new();
+    for (key, value) in fields.filter_map(|f| f.split_once('=')) {
+        match key {
+            "src" => {
+                let src = IpAddr::from_str(value).ok()?;
+                directions.push(UnixConnectionDirection {
+                    src,
+                    dst: src,
+                    sport: None,
+                    dport: None,
+                    packets: 0,
+                    bytes: 0,
+                })
+            }
+            "dst" => directions.last_mut()?.dst = IpAddr::from_str(value).ok()?,
+            "sport" => directions.last_mut()?.sport = value.parse().ok(),
+            "dport" => directions.last_mut()?.dport = value.parse().ok(),
+            "packets" => directions.last_mut()?.packets = value.parse().ok()?,
+            "bytes" => directions.last_mut()?.bytes = value.parse().ok()?,
+            _ => {}
+        }
+    }
+    let mut directions = directions.into_iter();
+    match (directions.next(), directions.next()) {
+        (Some(original), Some(reply)) => Some(UnixConnection {
+            protocol,
+            original,
+            reply,
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sys::fixture;
+
+    fn ip(value: &str) -> IpAddr {
+        IpAddr::from_str(value).unwrap()
+    }
+
+    /// Addresses on the fixture LANs
+    fn is_client(ip: &IpAddr) -> bool {
+        let ip = ip.to_string();
+        ip.starts_with("192.168.1.") || ip.starts_with("2001:db8:1:")
+    }
+
+    #[test]
+    fn test_load() -> io::Result<()> {
+        let table = UnixConntrackTable::load(&fixture("basic"))?;
+        assert_eq!(table.connections().len(), 6);
+        let forward = &table.connections()[3];
+        assert_eq!(forward.protocol, "tcp");
+        assert_eq!(forward.original.dport, Some(8443));
+        assert_eq!(forward.reply.src, ip("192.168.1.101"));
+
+        // The first sample has nothing expired yet
+        let mut accounting = UnixConntrackAccounting::default();
+        accounting.update(&table, is_client);
+        let laptop = accounting.get(&ip("192.168.1.100"));
+        assert_eq!(laptop.connections, 2);
+        assert_eq!(laptop.tx_bytes, 12000);
+        assert_eq!(laptop.rx_bytes, 353000);
+        assert_eq!(laptop.tx_packets, 140);
+        assert_eq!(laptop.rx_packets, 270);
+
+        // Outbound DNS plus a port forward to 443
+        let printer = accounting.get(&ip("192.168.1.101"));
+        assert_eq!(printer.connections, 2);
+        assert_eq!(printer.tx_bytes, 72 + 6000);
+        assert_eq!(printer.rx_bytes, 120 + 1000);
+
+        // Accounting disabled for this entry
+        let phone = accounting.get(&ip("192.168.1.103"));
+        assert_eq!(phone.connections, 1);
+        assert_eq!(phone.rx_bytes, 0);
+
+        let v6 = accounting.get(&ip("2001:db8:1::64"));
+        assert_eq!(v6.tx_bytes, 500);
+        assert_eq!(v6.rx_bytes, 900);
+
+        assert_eq!(
+            accounting.get(&ip("192.168.1.250")),
+            UnixConntrackCounters::default()
+        );
+        // Remote ends are not accounted
+        assert_eq!(accounting.totals.len(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_accounting() {
+        let line = |sport: u16, tx: usize, rx: usize| {
+            format!(
+                "ipv4 2 tcp 6 431999 ESTABLISHED src=192.168.1.100 dst=1.1.1.1 sport={} dport=443 \
+                 packets=1 bytes={} src=1.1.1.1 dst=203.0.113.2 sport=443 dport={} packets=1 \
+                 bytes={} [ASSURED] mark=0 zone=0 use=2",
+                sport, tx, sport, rx
+            )
+        };
+        let laptop = ip("192.168.1.100");
+        let mut accounting = UnixConntrackAccounting::default();
+        let sample = |lines: &[String]| UnixConntrackTable::parse(&lines.join("\n"));
+
+        accounting.update(
+            &sample(&[line(40000, 100, 1000), line(40001, 50, 500)]),
+            is_client,
+        );
+        assert_eq!(accounting.get(&laptop).tx_bytes, 150);
+        assert_eq!(accounting.get(&laptop).connections, 2);
+
+        // 40001 expired, 40000 kept counting
+        accounting.update(&sample(&[line(40000, 200, 2000)]), is_client);
+        let counters = accounting.get(&laptop);
+        assert_eq!(counters.connections, 1);
+        assert_eq!(counters.tx_bytes, 250);
+        assert_eq!(counters.rx_bytes, 2500);
+
+        // A new connection reused the 40000 tuple
+        accounting.update(&sample(&[line(40000, 10, 10)]), is_client);
+        assert_eq!(accounting.get(&laptop).tx_bytes, 260);
+        assert_eq!(
+            accounting.get(&ip("1.1.1.1")),
+            UnixConntrackCounters::default()
+        );
+
+        accounting.update(&UnixConntrackTable::default(), is_client);
+        let counters = accounting.get(&laptop);
+        assert_eq!(counters.connections, 0);
+        assert_eq!(counters.rx_bytes, 2510);
+
+        // Gone long enough to be forgotten
+        for _ in 1..EXPIRE_AFTER_SAMPLES {
+            accounting.update(&UnixConntrackTable::default(), is_client);
+        }
+        assert_eq!(accounting.get(&laptop), UnixConntrackCounters::default());
+        assert!(accounting.expired.is_empty());
+        assert!(accounting.idle.is_empty());
+    }
+}
@@ -1,22 +1,107 @@
-use pnet::{
-    datalink::{interfaces, NetworkInterface},
-    util::MacAddr,
-};
+use crate::sys::SysRoot;
+use pnet::{datalink::interfaces, util::MacAddr};
 use std::ffi::OsString;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::str::FromStr;
-use std::{fs, io, path};
+use std::time::{Duration, Instant};
+use std::{fmt, fs, io, path};
+
+// ===== Interface =====
+
+/// Index and addresses the OS has for an interface.
+#[derive(PartialEq, Clone, Debug)]
+pub(crate) struct UnixNetworkInterface {
+    pub name: String,
+    pub index: u32,
+    pub ips: Vec<UnixNetworkAddress>,
+}
+
+impl UnixNetworkInterface {
+    /// List the interfaces of the running system
+    pub fn list() -> Vec<Self> {
+        interfaces()
+            .into_iter()
+            .map(|i| UnixNetworkInterface {
+                name: i.name,
+                index: i.index,
+                ips: i
+                    .ips
+                    .iter()
+                    .map(|ip| UnixNetworkAddress::new(ip.ip(), ip.prefix()))
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+/// An address with its prefix length
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub(crate) struct UnixNetworkAddress {
+    pub ip: IpAddr,
+    pub prefix: u8,
+}
+
+impl UnixNetworkAddress {
+    pub fn new(ip: IpAddr, prefix: u8) -> Self {
+        UnixNetworkAddress { ip, prefix }
+    }
+
+    /// Whether an address is in this address's subnet
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.ip, ip, self.netmask()) {
+            (IpAddr::V4(net), IpAddr::V4(ip), IpAddr::V4(mask)) => {
+                u32::from(net) & u32::from(mask) == u32::from(*ip) & u32::from(mask)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip), IpAddr::V6(mask)) => {
+                u128::from(net) & u128::from(mask) == u128::from(*ip) & u128::from(mask)
+            }
+            _ => false,
+        }
+    }
+
+    pub fn netmask(&self) -> IpAddr {
+        match self.ip {
+            IpAddr::V4(_) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                IpAddr::V4(Ipv4Addr::from(mask))
+            }
+            IpAddr::V6(_) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                IpAddr::V6(Ipv6Addr::from(mask))
+            }
+        }
+    }
+}
+
+impl fmt::Display for UnixNetworkAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.ip, self.prefix)
+    }
+}
+
+// ===== Device =====
 
 #[derive(PartialEq, Clone, Debug)]
 pub(crate) struct UnixNetworkDevice {
+    root: SysRoot,
     name: String,
     mac: MacAddr,
-    interface: NetworkInterface,
+    interface: UnixNetworkInterface,
+    kind: UnixNetworkDeviceKind,
     statistics: UnixNetworkDeviceStatistics,
+    sampled_at: Instant,
+    rates: UnixNetworkDeviceRates,
+    link: UnixNetworkDeviceLink,
+    up_since: Option<Instant>,
 }
 
 impl UnixNetworkDevice {
-    pub fn new(name: &String) -> io::Result<UnixNetworkDevice> {
-        let device_path = path::Path::new("/sys/class/net").join(name);
+    pub fn new(
+        root: &SysRoot,
+        interfaces: &[UnixNetworkInterface],
+        name: &str,
+    ) -> io::Result<UnixNetworkDevice> {
+        let device_path = root.path("/sys/class/net").join(name);
         if !device_path.as_path().is_dir() {
             return Err(io::Error::new(
                 io::ErrorKind::InvalidInput,
@@ -34,41 +119,115 @@ impl UnixNetworkDevice {
         let mac = MacAddr::from_str(mac_string.trim())
             .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
 
-        let interface = interfaces().into_iter().filter(|i| i.name == *name).next();
-        if interface.is_none() {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                format!("Unable to locate NetworkInterface {}", name),
-            ));
-        }
-        let interface = interface.unwrap();
+        let interface = match interfaces.iter().find(|i| i.name == name) {
+            Some(interface) => interface.clone(),
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Unable to locate NetworkInterface {}", name),
+                ))
+            }
+        };
 
-        let statistics = UnixNetworkDeviceStatistics::new(name);
+        let kind = UnixNetworkDeviceKind::new(root, name);
+        let statistics = UnixNetworkDeviceStatistics::new(root, name);
+        let link = UnixNetworkDeviceLink::new(root, name);
+        let up_since = if link.is_up() {
+            Some(Instant::now())
+        } else {
+            None
+        };
 
         Ok(UnixNetworkDevice {
-            name: name.clone(),
+            root: root.clone(),
+            name: name.to_string(),
             mac,
             interface,
+            kind,
             statistics,
+            sampled_at: Instant::now(),
+            rates: UnixNetworkDeviceRates::default(),
+            link,
+            up_since,
         })
     }
 
-    pub fn refresh(&mut self) -> io::Result<()> {
-        self.statistics = UnixNetworkDeviceStatistics::new(&self.name);
+    /// Re-read statistics and link state, and pick up address changes from `interfaces`.
+    /// Rates are computed against the statistics of the previous refresh.
+    pub fn refresh(&mut self, interfaces: &[UnixNetworkInterface]) -> io::Result<()> {
+        let mut recreated = false;
+        if let Some(interface) = interfaces.iter().find(|i| i.name == self.name) {
+            // A new index means the interface was deleted and created again
+            recreated = interface.index != self.interface.index;
+            self.interface = interface.clone();
+        }
+        self.kind = UnixNetworkDeviceKind::new(&self.root, &self.name);
+        let statistics = UnixNetworkDeviceStatistics::new(&self.root, &self.name);
+        let sampled_at = Instant::now();
+        self.rates = if recreated {
+            UnixNetworkDeviceRates::default()
+        } else {
+            UnixNetworkDeviceRates::between(
+                &self.statistics,
+                &statistics,
+                sampled_at.duration_since(self.sampled_at),
+            )
+        };
+        self.statistics = statistics;
+        self.sampled_at = sampled_at;
+        let link = UnixNetworkDeviceLink::new(&self.root, &self.name);
+        if !link.is_up() {
+            self.up_since = None;
+        } else if self.up_since.is_none() || link.carrier_changes != self.link.carrier_changes {
+            // The link came up, or flapped since the last refresh
+            self.up_since = Some(Instant::now());
+        }
+        self.link = link;
         Ok(())
     }
 
-    pub fn list_devices() -> io::Result<Vec<Self>> {
-        let device_names = fs::read_dir("/sys/class/net")?
-            .filter(|dn| dn.is_ok())
-            .map(|dn| dn.unwrap())
-            .filter(|dn| dn.path().is_dir() && dn.file_name() != OsString::from("lo"))
-            .map(|dn| String::from(dn.file_name().to_str().unwrap()))
-            .map(|dn| Self::new(&dn))
-            .filter(|d| d.is_ok())
-            .map(|d| d.unwrap())
+    pub fn list_devices(
+        root: &SysRoot,
+        interfaces: &[UnixNetworkInterface],
+    ) -> io::Result<Vec<Self>> {
+        let mut devices: Vec<Self> = Self::list_names(root)?
+            .iter()
+            .filter_map(|name| Self::new(root, interfaces, name).ok())
             .collect();
-        Ok(device_names)
+        devices.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(devices)
+    }
+
+    /// Refresh `devices`, dropping removed devices and adding hot-plugged ones.
+    /// Known devices are refreshed in place so rates and uptime carry over.
+    /// Returns true if a device was added or removed.
+    pub fn refresh_devices(
+        root: &SysRoot,
+        interfaces: &[UnixNetworkInterface],
+        devices: &mut Vec<Self>,
+    ) -> io::Result<bool> {
+        let names = Self::list_names(root)?;
+        let known: Vec<String> = devices.iter().map(Self::name).collect();
+        devices.retain(|device| names.contains(&device.name));
+        for device in devices.iter_mut() {
+            device.refresh(interfaces)?;
+        }
+        for name in names.iter().filter(|name| !known.contains(name)) {
+            if let Ok(device) = Self::new(root, interfaces, name) {
+                devices.push(device);
+            }
+        }
+        devices.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(devices.iter().map(Self::name).collect::<Vec<String>>() != known)
+    }
+
+    /// Names of the devices in `/sys/class/net`, except loopback
+    fn list_names(root: &SysRoot) -> io::Result<Vec<String>> {
+        Ok(fs::read_dir(root.path("/sys/class/net"))?
+            .filter_map(|dn| dn.ok())
+            .filter(|dn| dn.path().is_dir() && dn.file_name() != OsString::from("lo"))
+            .filter_map(|dn| dn.file_name().to_str().map(String::from))
+            .collect())
     }
 
     pub fn name(&self) -> String {
@@ -79,18 +238,170 @@ impl UnixNetworkDevice {
         self.mac
     }
 
-    pub fn interface(&self) -> NetworkInterface {
+    pub fn interface(&self) -> UnixNetworkInterface {
         self.interface.clone()
     }
 
+    pub fn kind(&self) -> &UnixNetworkDeviceKind {
+        &self.kind
+    }
+
     pub fn statistics(&self) -> UnixNetworkDeviceStatistics {
         self.statistics.clone()
     }
+
+    /// Rates between the last two refreshes, zero before the first refresh
+    pub fn rates(&self) -> UnixNetworkDeviceRates {
+        self.rates
+    }
+
+    pub fn link(&self) -> UnixNetworkDeviceLink {
+        self.link.clone()
+    }
+
+    /// How long the link has been up
+    pub fn uptime(&self) -> Duration {
+        self.up_since
+            .map(|up_since| up_since.elapsed())
+            .unwrap_or_default()
+    }
+}
+
+// ===== Kind =====
+
+/// What backs a network device
+#[derive(PartialEq, Clone, Debug)]
+pub(crate) enum UnixNetworkDeviceKind {
+    /// A NIC with a backing device
+    Physical,
+    /// 802.1Q sub-interface listed in `/proc/net/vlan/config`
+    Vlan {
+        parent: String,
+        id: u16,
+    },
+    Bridge {
+        members: Vec<String>,
+    },
+    Bond {
+        slaves: Vec<String>,
+    },
+    /// Tunnels, veths, and bridges or bonds without members
+    Virtual,
+}
+
+impl UnixNetworkDeviceKind {
+    pub fn new(root: &SysRoot, device_name: &str) -> Self {
+        if let Some((parent, id)) = vlan_parent(root, device_name) {
+            return UnixNetworkDeviceKind::Vlan { parent, id };
+        }
+        let device_path = root.path("/sys/class/net").join(device_name);
+        if device_path.join("bridge").is_dir() {
+            let mut members: Vec<String> = fs::read_dir(device_path.join("brif"))
+                .map(|entries| {
+                    entries
+                        .filter_map(|e| e.ok())
+                        .filter_map(|e| e.file_name().into_string().ok())
+                        .collect()
+                })
+                .unwrap_or_default();
+            members.sort();
+            return UnixNetworkDeviceKind::Bridge { members };
+        }
+        if let Ok(slaves) = fs::read_to_string(device_path.join("bonding").join("slaves")) {
+            return UnixNetworkDeviceKind::Bond {
+                slaves: slaves.split_whitespace().map(String::from).collect(),
+            };
+        }
+        if device_path.join("device").exists() {
+            UnixNetworkDeviceKind::Physical
+        } else {
+            UnixNetworkDeviceKind::Virtual
+        }
+    }
+
+    /// Bridge members or bond slaves
+    pub fn members(&self) -> &[String] {
+        match self {
+            UnixNetworkDeviceKind::Bridge { members } => members,
+            UnixNetworkDeviceKind::Bond { slaves } => slaves,
+            _ => &[],
+        }
+    }
+}
+
+/// Parent and VLAN ID of a VLAN sub-interface
+fn vlan_parent(root: &SysRoot, device_name: &str) -> Option<(String, u16)> {
+    let config = root.read_to_string("/proc/net/vlan/config").ok()?;
+    config.lines().skip(2).find_map(|line| {
+        let fields: Vec<&str> = line.split('|').map(|f| f.trim()).collect();
+        match fields.as_slice() {
+            [name, id, parent] if *name == device_name => {
+                Some((parent.to_string(), id.parse().ok()?))
+            }
+            _ => None,
+        }
+    })
+}
+
+// ===== Link =====
+
+const IFF_UP: usize = 0x1;
+
+#[derive(PartialEq, Clone, Debug, Default)]
+pub(crate) struct UnixNetworkDeviceLink {
+    /// Administratively enabled
+    pub enabled: bool,
+    /// Link speed in Mbps, unknown while the link is down
+    pub speed: Option<usize>,
+    pub full_duplex: bool,
+    pub operstate: String,
+    pub carrier: bool,
+    pub carrier_changes: usize,
+}
+
+impl UnixNetworkDeviceLink {
+    pub fn new(root: &SysRoot, device_name: &str) -> UnixNetworkDeviceLink {
+        let read_value = |attribute: &str| -> io::Result<String> {
+            let attribute_path = path::Path::new("/sys/class/net")
+                .join(device_name)
+                .join(attribute);
+            root.read_to_string(attribute_path)
+                .map(|v| v.trim().to_string())
+        };
+
+        let flags = read_value("flags")
+            .ok()
+            .and_then(|v| usize::from_str_radix(v.trim_start_matches("0x"), 16).ok())
+            .unwrap_or_default();
+        UnixNetworkDeviceLink {
+            enabled: flags & IFF_UP != 0,
+            // Reading speed fails or gives -1 while the link is down
+            speed: read_value("speed")
+                .ok()
+                .and_then(|v| usize::from_str(&v).ok()),
+            full_duplex: read_value("duplex").map(|v| v == "full").unwrap_or(false),
+            operstate: read_value("operstate").unwrap_or_default(),
+            carrier: read_value("carrier").map(|v| v == "1").unwrap_or(false),
+            carrier_changes: read_value("carrier_changes")
+                .ok()
+                .and_then(|v| usize::from_str(&v).ok())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Virtual devices report an unknown operstate, fall back to carrier for those.
+    pub fn is_up(&self) -> bool {
+        match self.operstate.as_str() {
+            "up" => true,
+            "unknown" => self.carrier,
+            _ => false,
+        }
+    }
 }
 
 // ===== Statistics =====
 
-#[derive(PartialOrd, PartialEq, Clone, Debug)]
+#[derive(PartialOrd, PartialEq, Clone, Debug, Default)]
 pub(crate) struct UnixNetworkDeviceStatistics {
     pub collisions: usize,
     pub multicast: usize,
@@ -119,15 +430,13 @@ pub(crate) struct UnixNetworkDeviceStatistics {
 }
 
 impl UnixNetworkDeviceStatistics {
-    pub fn new(device_name: &String) -> UnixNetworkDeviceStatistics {
+    pub fn new(root: &SysRoot, device_name: &str) -> UnixNetworkDeviceStatistics {
         let read_value = |statistic_name: &str| -> io::Result<usize> {
             let stat_path = path::Path::new("/sys/class/net")
                 .join(device_name)
                 .join("statistics")
                 .join(statistic_name);
-            let value = fs::read_to_string(stat_path.as_path())?;
-            usize::from_str(value.trim())
-                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+            root.read_value(stat_path)
         };
 
         UnixNetworkDeviceStatistics {
@@ -158,3 +467,285 @@ impl UnixNetworkDeviceStatistics {
         }
     }
 }
+
+// ===== Rates =====
+
+/// Per second rates from the difference of two statistics samples.
+#[derive(PartialEq, Copy, Clone, Debug, Default)]
+pub(crate) struct UnixNetworkDeviceRates {
+    pub rx_bytes: usize,
+    pub rx_packets: usize,
+    pub tx_bytes: usize,
+    pub tx_packets: usize,
+}
+
+impl UnixNetworkDeviceRates {
+    pub fn between(
+        previous: &UnixNetworkDeviceStatistics,
+        current: &UnixNetworkDeviceStatistics,
+        elapsed: Duration,
+    ) -> Self {
+        let seconds = elapsed.as_secs_f64();
+        if seconds <= 0.0 {
+            return Self::default();
+        }
+        let rate = |previous: usize, current: usize| {
+            (counter_delta(previous, current) as f64 / seconds).round() as usize
+        };
+        UnixNetworkDeviceRates {
+            rx_bytes: rate(previous.rx_bytes, current.rx_bytes),
+            rx_packets: rate(previous.rx_packets, current.rx_packets),
+            tx_bytes: rate(previous.tx_bytes, current.tx_bytes),
+            tx_packets: rate(previous.tx_packets, current.tx_packets),
+        }
+    }
+
+    /// Received rate in Mbit/s
+    pub fn rx_mbps(&self) -> f64 {
+        self.rx_bytes as f64 * 8.0 / 1_000_000.0
+    }
+
+    /// Transmitted rate in Mbit/s
+    pub fn tx_mbps(&self) -> f64 {
+        self.tx_bytes as f64 * 8.0 / 1_000_000.0
+    }
+}
+
+/// Increase of a counter that may be 32 bits wide on some drivers.
+/// A counter that drops from the upper half of the 32-bit range wrapped,
+/// any other drop is a reset and the current value is what was counted since.
+fn counter_delta(previous: usize, current: usize) -> usize {
+    const WRAP: u64 = 1 << 32;
+    let (previous, current) = (previous as u64, current as u64);
+    let delta = if current >= previous {
+        current - previous
+    } else if (WRAP / 2..WRAP).contains(&previous) {
+        WRAP - previous + current
+    } else {
+        current
+    };
+    delta as usize
+}
+
+/// Interfaces matching the `basic` fixture tree
+#[cfg(test)]
+pub(crate) fn fixture_interfaces() -> Vec<UnixNetworkInterface> {
+    vec![
+        UnixNetworkInterface {
+            name: "eth0".to_string(),
+            index: 2,
+            ips: vec![
+                UnixNetworkAddress::new(IpAddr::V4(Ipv4Addr::new(192, 168, 113, 10)), 24),
+                UnixNetworkAddress::new(
+                    IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 1, 0, 0, 0, 0, 0x10)),
+                    64,
+                ),
+            ],
+        },
+        UnixNetworkInterface {
+            name: "eth1".to_string(),
+            index: 3,
+            ips: vec![],
+        },
+        UnixNetworkInterface {
+            name: "docker0".to_string(),
+            index: 4,
+            ips: vec![],
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sys::fixture;
+
+    #[test]
+    fn test_list_devices() -> io::Result<()> {
+        let devices = UnixNetworkDevice::list_devices(&fixture("basic"), &fixture_interfaces())?;
+        let names: Vec<String> = devices.iter().map(|d| d.name()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "docker0".to_string(),
+                "eth0".to_string(),
+                "eth1".to_string()
+            ]
+        );
+        assert_eq!(
+            devices[1].mac(),
+            MacAddr::new(0x00, 0xde, 0xad, 0xbe, 0xef, 0x00)
+        );
+        assert_eq!(devices[0].kind(), &UnixNetworkDeviceKind::Virtual);
+        assert_eq!(devices[1].kind(), &UnixNetworkDeviceKind::Physical);
+        Ok(())
+    }
+
+    #[test]
+    fn test_refresh_devices() -> io::Result<()> {
+        let root = fixture("basic");
+        let interfaces = fixture_interfaces();
+        let mut devices = UnixNetworkDevice::list_devices(&root, &interfaces[..1])?;
+        let sampled_at = devices[0].sampled_at;
+        // eth1 and docker0 showed up after startup
+        assert!(UnixNetworkDevice::refresh_devices(
+            &root,
+            &interfaces,
+            &mut devices
+        )?);
+        let names: Vec<String> = devices.iter().map(|d| d.name()).collect();
+        assert_eq!(names, vec!["docker0", "eth0", "eth1"]);
+        assert!(devices[1].sampled_at > sampled_at);
+        assert!(!UnixNetworkDevice::refresh_devices(
+            &root,
+            &interfaces,
+            &mut devices
+        )?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_device_kind() {
+        let root = fixture("bridged");
+        let kind = |name: &str| UnixNetworkDeviceKind::new(&root, name);
+        assert_eq!(kind("eth0"), UnixNetworkDeviceKind::Physical);
+        assert_eq!(
+            kind("br0"),
+            UnixNetworkDeviceKind::Bridge {
+                members: vec!["eth1".to_string()]
+            }
+        );
+        assert_eq!(
+            kind("bond0"),
+            UnixNetworkDeviceKind::Bond {
+                slaves: vec!["eth2".to_string(), "eth3".to_string()]
+            }
+        );
+        assert_eq!(
+            kind("bond0.20"),
+            UnixNetworkDeviceKind::Vlan {
+                parent: "bond0".to_string(),
+                id: 20
+            }
+        );
+        assert_eq!(
+            UnixNetworkDeviceKind::new(&fixture("basic"), "docker0"),
+            UnixNetworkDeviceKind::Virtual
+        );
+    }
+
+    #[test]
+    fn test_missing_device() {
+        let root = fixture("basic");
+        assert!(UnixNetworkDevice::new(&root, &fixture_interfaces(), "eth9").is_err());
+        // Present in sysfs but unknown to the OS
+        assert!(UnixNetworkDevice::new(&root, &[], "eth0").is_err());
+    }
+
+    #[test]
+    fn test_link() {
+        let root = fixture("basic");
+        let wan = UnixNetworkDeviceLink::new(&root, "eth0");
+        assert!(wan.enabled);
+        assert!(wan.is_up());
+        assert_eq!(wan.speed, Some(1000));
+        assert!(wan.full_duplex);
+
+        let lan = UnixNetworkDeviceLink::new(&root, "eth1");
+        assert!(!lan.enabled);
+        assert!(!lan.is_up());
+        assert_eq!(lan.speed, None);
+        assert_eq!(lan.carrier_changes, 4);
+
+        let root = fixture("bridged");
+        let slave = UnixNetworkDeviceLink::new(&root, "eth2");
+        assert_eq!(slave.speed, Some(100));
+        assert!(!slave.full_duplex);
+        // Tunnels report an unknown operstate, carrier decides
+        let tunnel = UnixNetworkDeviceLink::new(&root, "tun0");
+        assert_eq!(tunnel.operstate, "unknown");
+        assert!(tunnel.is_up());
+        assert_eq!(tunnel.speed, None);
+    }
+
+    #[test]
+    fn test_link_uptime() -> io::Result<()> {
+        let root = fixture("basic");
+        let interfaces = fixture_interfaces();
+        let mut wan = UnixNetworkDevice::new(&root, &interfaces, "eth0")?;
+        let up_since = wan.up_since.expect("eth0 is up");
+        wan.refresh(&interfaces)?;
+        assert_eq!(wan.up_since, Some(up_since));
+
+        // The carrier changed since the last refresh, the link flapped
+        wan.link.carrier_changes = 0;
+        std::thread::sleep(Duration::from_millis(5));
+        wan.refresh(&interfaces)?;
+        assert!(wan.up_since > Some(up_since));
+
+        let lan = UnixNetworkDevice::new(&root, &interfaces, "eth1")?;
+        assert_eq!(lan.up_since, None);
+        assert_eq!(lan.uptime(), Duration::default());
+        Ok(())
+    }
+
+    #[test]
+    fn test_statistics() {
+        let stats = UnixNetworkDeviceStatistics::new(&fixture("basic"), "eth0");
+        assert_eq!(stats.rx_bytes, 123456789);
+        assert_eq!(stats.rx_packets, 98765);
+        assert_eq!(stats.rx_dropped, 12);
+        assert_eq!(stats.rx_errors, 2);
+        assert_eq!(stats.tx_bytes, 23456789);
+        assert_eq!(stats.tx_packets, 54321);
+        assert_eq!(stats.tx_dropped, 3);
+        // Missing statistics read as zero
+        assert_eq!(stats.rx_nohandler, 0);
+    }
+
+    #[test]
+    fn test_rates() {
+        let sample = |rx_bytes: usize, tx_bytes: usize| UnixNetworkDeviceStatistics {
+            rx_bytes,
+            tx_bytes,
+            ..Default::default()
+        };
+        let elapsed = Duration::from_secs(10);
+        let rates =
+            UnixNetworkDeviceRates::between(&sample(1000, 500), &sample(11000, 2500), elapsed);
+        assert_eq!(rates.rx_bytes, 1000);
+        assert_eq!(rates.tx_bytes, 200);
+        assert_eq!(rates.rx_mbps(), 0.008);
+
+        // 32-bit counter wrapped
+        let wrapped =
+            UnixNetworkDeviceRates::between(&sample(0xffff_fc18, 0), &sample(9000, 0), elapsed);
+        assert_eq!(wrapped.rx_bytes, 1000);
+
+        // Counters reset, e.g. a driver reload
+        let reset = UnixNetworkDeviceRates::between(&sample(50000, 0), &sample(2000, 0), elapsed);
+        assert_eq!(reset.rx_bytes, 200);
+        assert_eq!(
+            UnixNetworkDeviceRates::between(&sample(0, 0), &sample(1000, 0), Duration::default()),
+            UnixNetworkDeviceRates::default()
+        );
+    }
+
+    #[test]
+    fn test_address_display() {
+        let interfaces = fixture_interfaces();
+        let ips: Vec<String> = interfaces[0].ips.iter().map(|ip| ip.to_string()).collect();
+        assert_eq!(ips, vec!["192.168.113.10/24", "2001:db8:1::10/64"]);
+    }
+
+    #[test]
+    fn test_netmask() {
+        let v4 = UnixNetworkAddress::new(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)), 24);
+        assert_eq!(v4.netmask(), IpAddr::V4(Ipv4Addr::new(255, 255, 255, 0)));
+        let v6 = UnixNetworkAddress::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 64);
+        assert_eq!(
+            v6.netmask(),
+            IpAddr::V6(Ipv6Addr::new(0xffff, 0xffff, 0xffff, 0xffff, 0, 0, 0, 0))
+        );
+    }
+}
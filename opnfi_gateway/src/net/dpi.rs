@@ -0,0 +1,459 @@
+use crate::net::conntrack::{UnixConnectionKey, UnixConntrackTable};
+use crate::settings::DpiSettings;
+use crate::sys::SysRoot;
+use lib_opnfi::inform::payload::stats::{
+    OpnFiInformDpiCategoryStat, OpnFiInformDpiClientStats, OpnFiInformDpiStat,
+    OpnFiInformDpiStatsTable,
+};
+use pnet::util::MacAddr;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+// Controller DPI categories
+const CAT_INSTANT_MESSAGING: u32 = 0;
+const CAT_P2P: u32 = 1;
+const CAT_FILE_TRANSFER: u32 = 3;
+const CAT_STREAMING: u32 = 4;
+const CAT_MAIL: u32 = 5;
+const CAT_VOIP: u32 = 6;
+const CAT_GAMES: u32 = 8;
+const CAT_REMOTE_ACCESS: u32 = 10;
+const CAT_TUNNELS: u32 = 11;
+const CAT_WEB: u32 = 13;
+const CAT_NETWORK: u32 = 18;
+const CAT_SOCIAL: u32 = 24;
+const CAT_UNKNOWN: u32 = 255;
+
+/// Application id of every stat. Applications are not identified, traffic is only told
+/// apart by category and each category reports one unknown application.
+pub(crate) const APP_UNKNOWN: u32 = 0;
+
+// ===== Signatures =====
+
+/// Protocol and port range a category is recognised by
+struct DpiSignature {
+    name: &'static str,
+    protocol: &'static str,
+    ports: (u16, u16),
+    cat: u32,
+}
+
+const fn signature(
+    name: &'static str,
+    protocol: &'static str,
+    ports: (u16, u16),
+    cat: u32,
+) -> DpiSignature {
+    DpiSignature {
+        name,
+        protocol,
+        ports,
+        cat,
+    }
+}
+
+const SIGNATURES: &[DpiSignature] = &[
+    signature("HTTP", "tcp", (80, 80), CAT_WEB),
+    signature("HTTPS", "tcp", (443, 443), CAT_WEB),
+    signature("QUIC", "udp", (443, 443), CAT_WEB),
+    signature("HTTP-Proxy", "tcp", (8080, 8080), CAT_WEB),
+    signature("DNS", "udp", (53, 53), CAT_NETWORK),
+    signature("DNS", "tcp", (53, 53), CAT_NETWORK),
+    signature("DoT", "tcp", (853, 853), CAT_NETWORK),
+    signature("NTP", "udp", (123, 123), CAT_NETWORK),
+    signature("ICMP", "icmp", (0, 0), CAT_NETWORK),
+    signature("ICMPv6", "icmpv6", (0, 0), CAT_NETWORK),
+    signature("SSH", "tcp", (22, 22), CAT_REMOTE_ACCESS),
+    signature("RDP", "tcp", (3389, 3389), CAT_REMOTE_ACCESS),
+    signature("VNC", "tcp", (5900, 5900), CAT_REMOTE_ACCESS),
+    signature("SMTP", "tcp", (25, 25), CAT_MAIL),
+    signature("SMTPS", "tcp", (465, 465), CAT_MAIL),
+    signature("Submission", "tcp", (587, 587), CAT_MAIL),
+    signature("IMAP", "tcp", (143, 143), CAT_MAIL),
+    signature("IMAPS", "tcp", (993, 993), CAT_MAIL),
+    signature("POP3", "tcp", (110, 110), CAT_MAIL),
+    signature("POP3S", "tcp", (995, 995), CAT_MAIL),
+    signature("FTP", "tcp", (21, 21), CAT_FILE_TRANSFER),
+    signature("RTMP", "tcp", (1935, 1935), CAT_STREAMING),
+    signature("RTSP", "tcp", (554, 554), CAT_STREAMING),
+    signature("SIP", "udp", (5060, 5061), CAT_VOIP),
+    signature("SIP", "tcp", (5060, 5061), CAT_VOIP),
+    signature("STUN", "udp", (3478, 3478), CAT_VOIP),
+    signature("XMPP", "tcp", (5222, 5222), CAT_INSTANT_MESSAGING),
+    signature("BitTorrent", "tcp", (6881, 6889), CAT_P2P),
+    signature("BitTorrent", "udp", (6881, 6889), CAT_P2P),
+    signature("Steam", "udp", (27015, 27030), CAT_GAMES),
+    signature("OpenVPN", "udp", (1194, 1194), CAT_TUNNELS),
+    signature("WireGuard", "udp", (51820, 51820), CAT_TUNNELS),
+    signature("IPsec", "udp", (4500, 4500), CAT_TUNNELS),
+];
+
+/// Categorise a connection by protocol and the port of the service
+pub(crate) fn classify(protocol: &str, port: Option<u16>) -> u32 {
+    let port = port.unwrap_or_default();
+    SIGNATURES
+        .iter()
+        .find(|s| s.protocol == protocol && s.ports.0 <= port && port <= s.ports.1)
+        .map(|s| s.cat)
+        .unwrap_or(CAT_UNKNOWN)
+}
+
+/// Map an nDPI protocol and category, e.g. `QUIC.YouTube` and `Media`, to a category.
+/// Protocols known to the signatures keep their category.
+pub(crate) fn classify_ndpi(protocol: &str, category: &str) -> u32 {
+    let name = protocol.rsplit('.').next().unwrap_or(protocol);
+    if let Some(s) = SIGNATURES
+        .iter()
+        .find(|s| s.name.eq_ignore_ascii_case(name))
+    {
+        return s.cat;
+    }
+    match category {
+        "Web" => CAT_WEB,
+        "Media" | "Streaming" | "Video" | "Music" => CAT_STREAMING,
+        "VoIP" => CAT_VOIP,
+        "Chat" => CAT_INSTANT_MESSAGING,
+        "Email" => CAT_MAIL,
+        "Download" | "Download-FileTransfer-FileSharing" | "FileSharing" | "Cloud" => {
+            CAT_FILE_TRANSFER
+        }
+        "Game" => CAT_GAMES,
+        "Network" => CAT_NETWORK,
+        "RemoteAccess" => CAT_REMOTE_ACCESS,
+        "VPN" => CAT_TUNNELS,
+        "SocialNetwork" => CAT_SOCIAL,
+        _ => CAT_UNKNOWN,
+    }
+}
+
+// ===== Flows =====
+
+/// Traffic of one client and category, tx is sent by the client.
+#[derive(PartialEq, Clone, Debug)]
+pub(crate) struct DpiFlow {
+    /// Identifies the flow across samples
+    pub key: UnixConnectionKey,
+    pub client: IpAddr,
+    pub cat: u32,
+    pub rx_bytes: usize,
+    pub rx_packets: usize,
+    pub tx_bytes: usize,
+    pub tx_packets: usize,
+}
+
+/// Flows from conntrack, the client is the initiator unless the connection was port forwarded.
+pub(crate) fn conntrack_flows(conntrack: &UnixConntrackTable) -> Vec<DpiFlow> {
+    conntrack
+        .connections()
+        .iter()
+        .map(|c| {
+            let (client, server, port) = if c.reply.src != c.original.dst {
+                (&c.reply, &c.original, c.reply.sport)
+            } else {
+                (&c.original, &c.reply, c.original.dport)
+            };
+            DpiFlow {
+                key: c.key(),
+                client: client.src,
+                cat: classify(&c.protocol, port),
+                rx_bytes: server.bytes,
+                rx_packets: server.packets,
+                tx_bytes: client.bytes,
+                tx_packets: client.packets,
+            }
+        })
+        .collect()
+}
+
+/// Flows from an nDPI JSON export with one flow per line, invalid lines are skipped.
+pub(crate) fn ndpi_flows(data: &str) -> Vec<DpiFlow> {
+    data.lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .filter_map(|flow| {
+            let count = |key: &str| flow[key].as_u64().unwrap_or_default() as usize;
+            let port = |key: &str| flow[key].as_u64().map(|port| port as u16);
+            let client = IpAddr::from_str(flow["src_ip"].as_str()?).ok()?;
+            let server = flow["dest_ip"]
+                .as_str()
+                .and_then(|ip| IpAddr::from_str(ip).ok())
+                .unwrap_or(client);
+            Some(DpiFlow {
+                key: (
+                    flow["proto"].as_str().unwrap_or_default().to_lowercase(),
+                    client,
+                    server,
+                    port("src_port"),
+                    port("dst_port"),
+                ),
+                client,
+                cat: classify_ndpi(
+                    flow["ndpi"]["proto"].as_str().unwrap_or_default(),
+                    flow["ndpi"]["category"].as_str().unwrap_or_default(),
+                ),
+                rx_bytes: count("dst2src_bytes"),
+                rx_packets: count("dst2src_packets"),
+                tx_bytes: count("src2dst_bytes"),
+                tx_packets: count("src2dst_packets"),
+            })
+        })
+        .collect()
+}
+
+// ===== Stats =====
+
+/// Category traffic per LAN client across samples.
+/// Flows missing from a sample ended, their last counters are kept so totals only grow.
+#[derive(PartialEq, Clone, Debug, Default)]
+pub(crate) struct DpiStats {
+    /// Flows of the last sample
+    flows: HashMap<UnixConnectionKey, DpiFlow>,
+    /// Traffic of flows that ended
+    ended: BTreeMap<IpAddr, BTreeMap<u32, OpnFiInformDpiStat>>,
+    /// Ended and current traffic
+    clients: BTreeMap<IpAddr, BTreeMap<u32, OpnFiInformDpiStat>>,
+}
+
+impl DpiStats {
+    /// Sample the configured nDPI export, or classify conntrack when there is none.
+    pub fn collect<F>(
+        &mut self,
+        root: &SysRoot,
+        settings: &DpiSettings,
+        conntrack: &UnixConntrackTable,
+        is_client: F,
+    ) -> io::Result<()>
+    where
+        F: Fn(&IpAddr) -> bool,
+    {
+        let flows = match &settings.import_path {
+            Some(path) => ndpi_flows(&root.read_to_string(path)?),
+            None => conntrack_flows(conntrack),
+        };
+        self.update(flows, is_client);
+        Ok(())
+    }
+
+    /// Add a sample, only flows of a client on the LAN are counted.
+    pub fn update<F>(&mut self, flows: Vec<DpiFlow>, is_client: F)
+    where
+        F: Fn(&IpAddr) -> bool,
+    {
+        let flows: HashMap<UnixConnectionKey, DpiFlow> = flows
+            .into_iter()
+            .filter(|f| is_client(&f.client))
+            .map(|f| (f.key.clone(), f))
+            .collect();
+        for (key, previous) in self.flows.drain() {
+            let ended = match flows.get(&key) {
+                // Counters went backwards, a new flow reused the key
+                Some(current) => {
+                    current.rx_bytes < previous.rx_bytes || current.tx_bytes < previous.tx_bytes
+                }
+                None => true,
+            };
+            if ended {
+                add_flow(&mut self.ended, &previous);
+            }
+        }
+        let mut clients = self.ended.clone();
+        for flow in flows.values() {
+            add_flow(&mut clients, flow);
+        }
+        self.clients = clients;
+        self.flows = flows;
+    }
+
+    /// Per client stats, addresses without a known MAC are left out.
+    pub fn client_stats<F>(&self, mac: F) -> Vec<OpnFiInformDpiClientStats>
+    where
+        F: Fn(&IpAddr) -> Option<MacAddr>,
+    {
+        let mut by_mac: BTreeMap<String, BTreeMap<u32, OpnFiInformDpiStat>> = BTreeMap::new();
+        for (ip, cats) in self.clients.iter() {
+            if let Some(mac) = mac(ip) {
+                // A client with IPv4 and IPv6 addresses is reported once
+                let client = by_mac.entry(mac.to_string()).or_default();
+                for (cat, stat) in cats {
+                    merge(client.entry(*cat).or_insert_with(|| stat_for(*cat)), stat);
+                }
+            }
+        }
+        by_mac
+            .into_iter()
+            .map(|(mac, cats)| OpnFiInformDpiClientStats {
+                mac,
+                stats: cats.into_values().collect(),
+            })
+            .collect()
+    }
+
+    /// Totals per category over every client, each with its one unknown application
+    pub fn table(&self) -> OpnFiInformDpiStatsTable {
+        let mut by_app: BTreeMap<u32, OpnFiInformDpiStat> = BTreeMap::new();
+        for (cat, stat) in self.clients.values().flatten() {
+            merge(by_app.entry(*cat).or_insert_with(|| stat_for(*cat)), stat);
+        }
+        let by_cat = by_app
+            .values()
+            .map(|stat| OpnFiInformDpiCategoryStat {
+                cat: stat.cat,
+                apps: vec![stat.app],
+                rx_bytes: stat.rx_bytes,
+                rx_packets: stat.rx_packets,
+                tx_bytes: stat.tx_bytes,
+                tx_packets: stat.tx_packets,
+            })
+            .collect();
+        OpnFiInformDpiStatsTable {
+            by_app: by_app.into_values().collect(),
+            by_cat,
+        }
+    }
+}
+
+fn add_flow(clients: &mut BTreeMap<IpAddr, BTreeMap<u32, OpnFiInformDpiStat>>, flow: &DpiFlow) {
+    let stat = clients
+        .entry(flow.client)
+        .or_default()
+        .entry(flow.cat)
+        .or_insert_with(|| stat_for(flow.cat));
+    stat.rx_bytes += flow.rx_bytes;
+    stat.rx_packets += flow.rx_packets;
+    stat.tx_bytes += flow.tx_bytes;
+    stat.tx_packets += flow.tx_packets;
+}
+
+fn stat_for(cat: u32) -> OpnFiInformDpiStat {
+    OpnFiInformDpiStat {
+        app: APP_UNKNOWN,
+        cat,
+        ..OpnFiInformDpiStat::default()
+    }
+}
+
+fn merge(into: &mut OpnFiInformDpiStat, stat: &OpnFiInformDpiStat) {
+    into.rx_bytes += stat.rx_bytes;
+    into.rx_packets += stat.rx_packets;
+    into.tx_bytes += stat.tx_bytes;
+    into.tx_packets += stat.tx_packets;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sys::fixture;
+
+    fn ip(value: &str) -> IpAddr {
+        IpAddr::from_str(value).unwrap()
+    }
+
+    fn is_client(ip: &IpAddr) -> bool {
+        ip.to_string().starts_with("192.168.1.") || ip.to_string().starts_with("2001:db8:1:")
+    }
+
+    #[test]
+    fn test_conntrack_stats() -> io::Result<()> {
+        let root = fixture("basic");
+        let conntrack = UnixConntrackTable::load(&root)?;
+        let mut stats = DpiStats::default();
+        stats.collect(&root, &DpiSettings::default(), &conntrack, is_client)?;
+
+        let laptop = &stats.clients[&ip("192.168.1.100")];
+        assert_eq!(laptop.len(), 1);
+        assert_eq!(laptop[&CAT_WEB].rx_bytes, 353000);
+
+        // Port forward to the printer is its own HTTPS traffic
+        let printer = &stats.clients[&ip("192.168.1.101")];
+        assert_eq!(printer[&CAT_WEB].tx_bytes, 6000);
+        assert_eq!(printer[&CAT_NETWORK].tx_bytes, 72);
+
+        let table = stats.table();
+        let web = table.by_cat.iter().find(|c| c.cat == CAT_WEB).unwrap();
+        assert_eq!(web.apps, vec![APP_UNKNOWN]);
+        assert_eq!(web.rx_bytes, 353000 + 1000 + 900);
+        assert!(table.by_cat.iter().any(|c| c.cat == CAT_NETWORK));
+        assert!(table.by_app.iter().all(|s| s.app == APP_UNKNOWN));
+
+        // Traffic of other addresses is not counted
+        let laptop = ip("192.168.1.100");
+        let mut stats = DpiStats::default();
+        stats.collect(&root, &DpiSettings::default(), &conntrack, |ip| {
+            *ip == laptop
+        })?;
+        assert_eq!(stats.clients.len(), 1);
+        assert_eq!(stats.table().by_cat.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ndpi_stats() -> io::Result<()> {
+        let root = fixture("basic");
+        let settings = DpiSettings {
+            enabled: true,
+            import_path: Some("/var/lib/ndpi/flows.json".into()),
+        };
+        let mut stats = DpiStats::default();
+        stats.collect(&root, &settings, &UnixConntrackTable::default(), is_client)?;
+        let laptop_mac = MacAddr::new(0xaa, 0xbb, 0xcc, 0, 0, 1);
+        let clients = stats.client_stats(|client| {
+            if *client == ip("192.168.1.100") {
+                Some(laptop_mac)
+            } else {
+                None
+            }
+        });
+        assert_eq!(clients.len(), 1);
+        assert_eq!(clients[0].mac, laptop_mac.to_string());
+        assert_eq!(classify_ndpi("QUIC.YouTube", "Media"), CAT_STREAMING);
+        assert!(clients[0]
+            .stats
+            .iter()
+            .any(|s| s.cat == CAT_STREAMING && s.app == APP_UNKNOWN && s.rx_bytes == 2800000));
+        // Known to the signatures by name
+        assert_eq!(classify_ndpi("DNS", "Web"), classify("udp", Some(53)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cumulative_stats() {
+        let flow = |client: &str, sport: u16, rx_bytes: usize| DpiFlow {
+            key: (
+                "tcp".to_string(),
+                ip(client),
+                ip("93.184.216.34"),
+                Some(sport),
+                Some(443),
+            ),
+            client: ip(client),
+            cat: CAT_WEB,
+            rx_bytes,
+            rx_packets: 1,
+            tx_bytes: 100,
+            tx_packets: 1,
+        };
+        let rx_bytes = |stats: &DpiStats| stats.clients[&ip("192.168.1.100")][&CAT_WEB].rx_bytes;
+        let mut stats = DpiStats::default();
+        stats.update(
+            vec![
+                flow("192.168.1.100", 50000, 1000),
+                flow("192.168.1.100", 50001, 500),
+            ],
+            is_client,
+        );
+        assert_eq!(rx_bytes(&stats), 1500);
+        // 50001 ended
+        stats.update(vec![flow("192.168.1.100", 50000, 3000)], is_client);
+        assert_eq!(rx_bytes(&stats), 3500);
+        // 50000 was reused by a new connection
+        stats.update(vec![flow("192.168.1.100", 50000, 200)], is_client);
+        assert_eq!(rx_bytes(&stats), 3700);
+        stats.update(Vec::new(), is_client);
+        assert_eq!(rx_bytes(&stats), 3700);
+        // Ended flows of other addresses are not kept
+        stats.update(vec![flow("198.51.100.7", 50000, 1000)], is_client);
+        stats.update(Vec::new(), is_client);
+        assert_eq!(stats.clients.len(), 1);
+    }
+}
@@ -1,48 +1,130 @@
-use crate::net::device::UnixNetworkDevice;
-use lib_opnfi::inform::payload::net::OpnFiInformNetworkInterface;
+use crate::net::device::{UnixNetworkDevice, UnixNetworkDeviceKind};
+use lib_opnfi::inform::payload::net::{OpnFiInformInterfaceType, OpnFiInformNetworkInterface};
 
+pub(crate) mod clients;
+pub(crate) mod conntrack;
 pub(crate) mod device;
+pub(crate) mod dpi;
 pub(crate) mod nameservers;
+pub(crate) mod ports;
+pub(crate) mod routes;
+pub(crate) mod wan;
 
 impl From<device::UnixNetworkDevice> for OpnFiInformNetworkInterface {
     fn from(value: UnixNetworkDevice) -> Self {
         let interface = value.interface();
         let stats = value.statistics();
-        let ip = interface
-            .ips
-            .iter()
-            .filter(|ip| ip.is_ipv4())
-            .next()
-            .unwrap();
+        let rates = value.rates();
+        let link = value.link();
+        let ipv4 = interface.ips.iter().find(|ip| ip.ip.is_ipv4());
+        let kind = value.kind();
+        let (if_type, parent, vlan) = match kind {
+            UnixNetworkDeviceKind::Physical => (OpnFiInformInterfaceType::Ethernet, None, None),
+            UnixNetworkDeviceKind::Vlan { parent, id } => (
+                OpnFiInformInterfaceType::Vlan,
+                Some(parent.clone()),
+                Some(*id),
+            ),
+            UnixNetworkDeviceKind::Bridge { .. } => (OpnFiInformInterfaceType::Bridge, None, None),
+            UnixNetworkDeviceKind::Bond { .. } => (OpnFiInformInterfaceType::Bond, None, None),
+            UnixNetworkDeviceKind::Virtual => (OpnFiInformInterfaceType::Virtual, None, None),
+        };
         Self {
-            drops: stats.rx_dropped + stats.tx_dropped,
-            enabled: true,
-            full_duplex: true,
+            addresses: interface.ips.iter().map(|ip| ip.to_string()).collect(),
+            availability: None,
+            drops: 0,
+            enabled: link.enabled,
+            full_duplex: link.full_duplex,
             gateways: vec![],
-            ip: ip.ip().to_string(),
-            latency: 1,
+            ip: ipv4.map(|ip| ip.ip.to_string()).unwrap_or_default(),
+            ipv6_pd: vec![],
+            latency: 0,
             mac: value.mac().to_string(),
+            members: kind.members().to_vec(),
             name: value.name().to_string(),
             nameservers: vec![],
-            netmask: ip.mask().to_string(),
+            netmask: ipv4.map(|ip| ip.netmask().to_string()).unwrap_or_default(),
             num_port: interface.index as usize,
+            parent,
             rx_bytes: stats.rx_bytes,
+            rx_bytes_r: rates.rx_bytes,
             rx_dropped: stats.rx_dropped,
             rx_errors: stats.rx_errors,
             rx_multicast: 0,
             rx_packets: stats.rx_packets,
-            speed: 1000,
+            speed: link.speed.unwrap_or_default(),
             speedtest_lastrun: 0,
             speedtest_ping: 0,
             speedtest_status: "Idle".to_string(),
             tx_bytes: stats.tx_bytes,
+            tx_bytes_r: rates.tx_bytes,
             tx_dropped: stats.tx_dropped,
             tx_errors: stats.tx_errors,
             tx_packets: stats.tx_packets,
-            up: true,
-            uptime: 0,
-            xput_down: 0,
-            xput_up: 0,
+            if_type,
+            up: link.is_up(),
+            uptime: value.uptime().as_secs() as usize,
+            vlan,
+            xput_down: rates.rx_mbps().round() as usize,
+            xput_up: rates.tx_mbps().round() as usize,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::device::{fixture_interfaces, UnixNetworkInterface};
+    use crate::sys::fixture;
+    use std::io;
+
+    #[test]
+    fn test_inform_interface() -> io::Result<()> {
+        let root = fixture("basic");
+        let interfaces = fixture_interfaces();
+
+        let wan: OpnFiInformNetworkInterface =
+            UnixNetworkDevice::new(&root, &interfaces, "eth0")?.into();
+        assert_eq!(wan.ip, "192.168.113.10");
+        assert_eq!(wan.netmask, "255.255.255.0");
+        assert_eq!(
+            wan.addresses,
+            vec!["192.168.113.10/24", "2001:db8:1::10/64"]
+        );
+
+        // No addresses is reported as unconfigured
+        let lan: OpnFiInformNetworkInterface =
+            UnixNetworkDevice::new(&root, &interfaces, "eth1")?.into();
+        assert_eq!(lan.ip, "");
+        assert_eq!(lan.netmask, "");
+        assert!(lan.addresses.is_empty());
+        assert!(!lan.up);
+        Ok(())
+    }
+
+    #[test]
+    fn test_inform_vlan() -> io::Result<()> {
+        let interfaces: Vec<UnixNetworkInterface> = ["bond0", "bond0.20"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| UnixNetworkInterface {
+                name: name.to_string(),
+                index: i as u32 + 10,
+                ips: vec![],
+            })
+            .collect();
+        let root = fixture("bridged");
+
+        let vlan: OpnFiInformNetworkInterface =
+            UnixNetworkDevice::new(&root, &interfaces, "bond0.20")?.into();
+        assert_eq!(vlan.if_type, OpnFiInformInterfaceType::Vlan);
+        assert_eq!(vlan.parent, Some("bond0".to_string()));
+        assert_eq!(vlan.vlan, Some(20));
+
+        let bond: OpnFiInformNetworkInterface =
+            UnixNetworkDevice::new(&root, &interfaces, "bond0")?.into();
+        assert_eq!(bond.if_type, OpnFiInformInterfaceType::Bond);
+        assert_eq!(bond.members, vec!["eth2", "eth3"]);
+        Ok(())
+    }
+}
@@ -1,13 +1,14 @@
+use crate::sys::SysRoot;
 use regex::{self, Regex};
 use std::io::BufRead;
 use std::str::FromStr;
-use std::{fs, io, net::IpAddr, path};
+use std::{fs, io, net::IpAddr};
 
-pub(crate) fn get_nameservers() -> io::Result<Vec<IpAddr>> {
+pub(crate) fn get_nameservers(root: &SysRoot) -> io::Result<Vec<IpAddr>> {
     lazy_static! {
         static ref NAMESERVER_REGEX: Regex = Regex::new(r"^nameserver\s([\d.:a-f]+)$").unwrap();
     }
-    let resolv_path = path::Path::new("/etc/resolv.conf");
+    let resolv_path = root.path("/etc/resolv.conf");
     let mut nameservers = Vec::new();
     if resolv_path.exists() {
         let rdr = io::BufReader::new(fs::File::open(resolv_path)?);
@@ -26,3 +27,28 @@ pub(crate) fn get_nameservers() -> io::Result<Vec<IpAddr>> {
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sys::fixture;
+
+    #[test]
+    fn test_get_nameservers() -> io::Result<()> {
+        let nameservers = get_nameservers(&fixture("basic"))?;
+        assert_eq!(
+            nameservers,
+            vec![
+                IpAddr::from_str("1.1.1.1").unwrap(),
+                IpAddr::from_str("2606:4700:4700::1111").unwrap(),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_resolv_conf() {
+        let err = get_nameservers(&SysRoot::new("/nonexistent")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+}
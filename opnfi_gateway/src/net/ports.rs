@@ -0,0 +1,330 @@
+use crate::net::device::{UnixNetworkDevice, UnixNetworkDeviceKind};
+use lib_opnfi::inform::payload::net::OpnFiInformConfigPortTableItem;
+use lib_opnfi::model::OpnFiModelProfile;
+use std::collections::BTreeMap;
+
+// ===== Port =====
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Debug)]
+pub(crate) enum PortRole {
+    Wan,
+    Lan,
+}
+
+impl PortRole {
+    /// Role of a logical port name, anything not starting with WAN is a LAN.
+    pub fn from_name(name: &str) -> Self {
+        if name.to_uppercase().starts_with("WAN") {
+            PortRole::Wan
+        } else {
+            PortRole::Lan
+        }
+    }
+}
+
+/// A host interface reported to the controller as a gateway port.
+#[derive(PartialEq, Clone, Debug)]
+pub(crate) struct Port {
+    /// Logical port name, e.g. WAN or LAN2
+    pub name: String,
+    /// Host interface name
+    pub ifname: String,
+    pub role: PortRole,
+}
+
+impl Port {
+    pub fn new(name: &str, ifname: &str, role: PortRole) -> Self {
+        Port {
+            name: name.to_string(),
+            ifname: ifname.to_string(),
+            role,
+        }
+    }
+
+    /// Sort key placing WANs first, then ports by number so LAN2 comes before LAN10.
+    fn order(&self) -> (PortRole, u32, String) {
+        let number = self
+            .name
+            .trim_start_matches(|c: char| !c.is_ascii_digit())
+            .parse()
+            .unwrap_or(1);
+        (self.role, number, self.name.clone())
+    }
+}
+
+/// Port name without the number of a first port, LAN1 and LAN are the same port.
+fn canonical_name(name: &str) -> String {
+    let name = name.to_uppercase();
+    match name.strip_suffix('1') {
+        Some(base) if !base.is_empty() && base.chars().all(|c| c.is_ascii_alphabetic()) => {
+            base.to_string()
+        }
+        _ => name,
+    }
+}
+
+// ===== Detection =====
+
+/// Assign ports to the host interfaces.
+/// Explicitly named interfaces win, otherwise the interface holding the default route
+/// is the WAN and every other physical interface is a LAN.
+/// Named interfaces missing from the host are skipped with a warning.
+pub(crate) fn detect_ports(
+    devices: &[UnixNetworkDevice],
+    uplink: Option<&str>,
+    wan: Option<&str>,
+    lans: &[String],
+) -> Vec<Port> {
+    let exists = |name: &str| {
+        let found = devices.iter().any(|d| d.name() == name);
+        if !found {
+            warn!("Interface {} was not found, it will not be reported", name);
+        }
+        found
+    };
+
+    let wan = match wan {
+        Some(wan) => Some(wan).filter(|wan| exists(wan)),
+        None => uplink.filter(|uplink| devices.iter().any(|d| d.name() == *uplink)),
+    };
+    let lans: Vec<String> = if lans.is_empty() {
+        devices
+            .iter()
+            .filter(|d| is_lan_candidate(devices, d, wan) && Some(d.name().as_str()) != wan)
+            .map(|d| d.name())
+            .collect()
+    } else {
+        lans.iter().filter(|lan| exists(lan)).cloned().collect()
+    };
+
+    let mut ports = Vec::new();
+    if let Some(wan) = wan {
+        ports.push(Port::new("WAN", wan, PortRole::Wan));
+    }
+    for (i, lan) in lans.iter().enumerate() {
+        let name = match i {
+            0 => "LAN".to_string(),
+            i => format!("LAN{}", i + 1),
+        };
+        ports.push(Port::new(&name, lan, PortRole::Lan));
+    }
+    ports
+}
+
+/// Physical NICs, and bridges, bonds and VLANs built on them, are LAN candidates.
+/// Bridge members and bond slaves are covered by their master instead, VLANs on the WAN
+/// carry WAN traffic.
+fn is_lan_candidate(
+    devices: &[UnixNetworkDevice],
+    device: &UnixNetworkDevice,
+    wan: Option<&str>,
+) -> bool {
+    let name = device.name();
+    if devices.iter().any(|d| d.kind().members().contains(&name)) {
+        return false;
+    }
+    let backed = |name: &str| {
+        devices.iter().any(|d| {
+            d.name() == name
+                && matches!(
+                    d.kind(),
+                    UnixNetworkDeviceKind::Physical
+                        | UnixNetworkDeviceKind::Bond { .. }
+                        | UnixNetworkDeviceKind::Bridge { .. }
+                )
+        })
+    };
+    match device.kind() {
+        UnixNetworkDeviceKind::Physical => true,
+        UnixNetworkDeviceKind::Vlan { parent, .. } => {
+            Some(parent.as_str()) != wan && backed(parent)
+        }
+        UnixNetworkDeviceKind::Bridge { members }
+        | UnixNetworkDeviceKind::Bond { slaves: members } => members.iter().any(|m| backed(m)),
+        UnixNetworkDeviceKind::Virtual => false,
+    }
+}
+
+/// Ports from an explicit map of logical port names to host interfaces.
+/// Interfaces missing from the host are skipped with a warning.
+pub(crate) fn map_ports(
+    devices: &[UnixNetworkDevice],
+    map: &BTreeMap<String, String>,
+) -> Vec<Port> {
+    let mut ports: Vec<Port> = map
+        .iter()
+        .filter(|(_, ifname)| {
+            let found = devices.iter().any(|d| &d.name() == *ifname);
+            if !found {
+                warn!(
+                    "Interface {} was not found, it will not be reported",
+                    ifname
+                );
+            }
+            found
+        })
+        .map(|(name, ifname)| Port::new(name, ifname, PortRole::from_name(name)))
+        .collect();
+    ports.sort_by_key(|port| port.order());
+    ports
+}
+
+// ===== Model =====
+
+/// Port table in the layout of the emulated model, with the host interface of each port.
+/// Model ports without a host interface are left out.
+pub(crate) fn port_table(
+    profile: &OpnFiModelProfile,
+    ports: &[Port],
+) -> Vec<OpnFiInformConfigPortTableItem> {
+    profile
+        .ports
+        .iter()
+        .filter_map(|model_port| {
+            let port = ports
+                .iter()
+                .find(|port| canonical_name(&port.name) == canonical_name(model_port.name))?;
+            Some(OpnFiInformConfigPortTableItem::new(
+                model_port.name.to_string(),
+                port.ifname.clone(),
+            ))
+        })
+        .collect()
+}
+
+/// Ports the model has no place for, they are still reported in the interface table
+pub(crate) fn unplaced_ports<'a>(profile: &OpnFiModelProfile, ports: &'a [Port]) -> Vec<&'a Port> {
+    ports
+        .iter()
+        .filter(|port| {
+            profile
+                .ports
+                .iter()
+                .all(|model_port| canonical_name(model_port.name) != canonical_name(&port.name))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::device::{fixture_interfaces, UnixNetworkInterface};
+    use crate::sys::fixture;
+    use std::io;
+
+    fn devices() -> io::Result<Vec<UnixNetworkDevice>> {
+        UnixNetworkDevice::list_devices(&fixture("basic"), &fixture_interfaces())
+    }
+
+    #[test]
+    fn test_detect_ports() -> io::Result<()> {
+        let ports = detect_ports(&devices()?, Some("eth0"), None, &[]);
+        assert_eq!(
+            ports,
+            vec![
+                Port::new("WAN", "eth0", PortRole::Wan),
+                Port::new("LAN", "eth1", PortRole::Lan),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect_ports_without_default_route() -> io::Result<()> {
+        let ports = detect_ports(&devices()?, None, None, &[]);
+        assert_eq!(
+            ports,
+            vec![
+                Port::new("LAN", "eth0", PortRole::Lan),
+                Port::new("LAN2", "eth1", PortRole::Lan),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect_ports_bridged() -> io::Result<()> {
+        let interfaces: Vec<UnixNetworkInterface> = [
+            "eth0", "eth1", "eth2", "eth3", "br0", "bond0", "bond0.20", "br0.10", "eth0.100",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, name)| UnixNetworkInterface {
+            name: name.to_string(),
+            index: i as u32 + 2,
+            ips: vec![],
+        })
+        .collect();
+        let devices = UnixNetworkDevice::list_devices(&fixture("bridged"), &interfaces)?;
+        let ports = detect_ports(&devices, Some("eth0"), None, &[]);
+        let ifnames: Vec<&str> = ports.iter().map(|p| p.ifname.as_str()).collect();
+        // Bridge members and bond slaves are not reported on their own, VLANs on the WAN
+        // are not LANs
+        assert_eq!(ifnames, vec!["eth0", "bond0", "bond0.20", "br0", "br0.10"]);
+
+        // Without a WAN its VLAN is a LAN like any other
+        let ports = detect_ports(&devices, None, None, &[]);
+        assert!(ports.iter().any(|p| p.ifname == "eth0.100"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_explicit_ports() -> io::Result<()> {
+        let ports = detect_ports(
+            &devices()?,
+            Some("eth0"),
+            Some("eth1"),
+            &["docker0".to_string(), "eth9".to_string()],
+        );
+        assert_eq!(
+            ports,
+            vec![
+                Port::new("WAN", "eth1", PortRole::Wan),
+                Port::new("LAN", "docker0", PortRole::Lan),
+            ]
+        );
+        assert!(detect_ports(&devices()?, None, Some("eth9"), &["eth8".to_string()]).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_map_ports() -> io::Result<()> {
+        let map: BTreeMap<String, String> = vec![
+            ("LAN10", "eth9"),
+            ("LAN2", "docker0"),
+            ("LAN1", "eth1"),
+            ("WAN", "eth0"),
+        ]
+        .into_iter()
+        .map(|(name, ifname)| (name.to_string(), ifname.to_string()))
+        .collect();
+        assert_eq!(
+            map_ports(&devices()?, &map),
+            vec![
+                Port::new("WAN", "eth0", PortRole::Wan),
+                Port::new("LAN1", "eth1", PortRole::Lan),
+                Port::new("LAN2", "docker0", PortRole::Lan),
+            ]
+        );
+        assert_eq!(PortRole::from_name("wan2"), PortRole::Wan);
+        Ok(())
+    }
+
+    #[test]
+    fn test_port_table() {
+        let profile = OpnFiModelProfile::find("UGW3").unwrap();
+        let ports = vec![
+            Port::new("WAN", "enp1s0", PortRole::Wan),
+            Port::new("LAN1", "enp2s0", PortRole::Lan),
+            Port::new("LAN2", "enp3s0", PortRole::Lan),
+        ];
+        let item = |name: &str, ifname: &str| {
+            OpnFiInformConfigPortTableItem::new(name.to_string(), ifname.to_string())
+        };
+        assert_eq!(
+            port_table(profile, &ports),
+            vec![item("WAN", "enp1s0"), item("LAN", "enp2s0")]
+        );
+        assert_eq!(unplaced_ports(profile, &ports), vec![&ports[2]]);
+    }
+}
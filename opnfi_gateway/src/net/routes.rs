@@ -0,0 +1,208 @@
+use crate::sys::SysRoot;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::{io, path};
+
+const RTF_UP: u32 = 0x0001;
+const RTF_GATEWAY: u32 = 0x0002;
+const RTF_REJECT: u32 = 0x0200;
+
+// ===== Route =====
+
+#[derive(PartialEq, Clone, Debug)]
+pub(crate) struct UnixRoute {
+    pub interface: String,
+    pub destination: IpAddr,
+    pub prefix: u8,
+    pub gateway: Option<IpAddr>,
+    pub metric: u32,
+}
+
+impl UnixRoute {
+    pub fn is_default(&self) -> bool {
+        self.prefix == 0
+    }
+}
+
+// ===== Routing Table =====
+
+/// IPv4 and IPv6 routes read from `/proc/net/route` and `/proc/net/ipv6_route`.
+#[derive(PartialEq, Clone, Debug, Default)]
+pub(crate) struct UnixRoutingTable {
+    routes: Vec<UnixRoute>,
+}
+
+impl UnixRoutingTable {
+    pub fn new(routes: Vec<UnixRoute>) -> Self {
+        UnixRoutingTable { routes }
+    }
+
+    /// Load the routing table, hosts without IPv6 only get IPv4 routes.
+    pub fn load(root: &SysRoot) -> io::Result<Self> {
+        let mut routes = parse_ipv4_routes(&root.read_to_string("/proc/net/route")?);
+        let ipv6_path = path::Path::new("/proc/net/ipv6_route");
+        if root.path(ipv6_path).exists() {
+            routes.extend(parse_ipv6_routes(&root.read_to_string(ipv6_path)?));
+        }
+        Ok(Self::new(routes))
+    }
+
+    /// Gateways reachable through an interface
+    pub fn gateways(&self, interface: &str) -> Vec<IpAddr> {
+        let mut gateways = Vec::new();
+        for route in self.routes.iter().filter(|r| r.interface == interface) {
+            if let Some(gateway) = route.gateway {
+                if !gateways.contains(&gateway) {
+                    gateways.push(gateway);
+                }
+            }
+        }
+        gateways
+    }
+
+    /// The IPv4 default route with the lowest metric, falling back to IPv6.
+    pub fn default_route(&self) -> Option<&UnixRoute> {
+        let best = |v4: bool| {
+            self.routes
+                .iter()
+                .filter(|r| r.is_default() && r.destination.is_ipv4() == v4)
+                .min_by_key(|r| r.metric)
+        };
+        best(true).or_else(|| best(false))
+    }
+
+    /// Gateway of the lowest metric default route through an interface
+    pub fn default_gateway(&self, interface: &str) -> Option<IpAddr> {
+        self.routes
+            .iter()
+            .filter(|r| r.is_default() && r.interface == interface)
+            .min_by_key(|r| (r.destination.is_ipv6(), r.metric))
+            .and_then(|r| r.gateway)
+    }
+
+    /// Interface holding the default route
+    pub fn uplink(&self) -> Option<String> {
+        self.default_route().map(|r| r.interface.clone())
+    }
+}
+
+// ===== Parsing =====
+
+/// Parse `/proc/net/route`, addresses are hex in host byte order.
+pub(crate) fn parse_ipv4_routes(data: &str) -> Vec<UnixRoute> {
+    let parse_addr = |value: &str| {
+        u32::from_str_radix(value, 16)
+            .ok()
+            .map(|v| Ipv4Addr::from(v.to_ne_bytes()))
+    };
+    data.lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 8 {
+                return None;
+            }
+            let flags = u32::from_str_radix(fields[3], 16).ok()?;
+            if flags & RTF_UP == 0 || flags & RTF_REJECT != 0 {
+                return None;
+            }
+            let gateway = parse_addr(fields[2])?;
+            let mask = parse_addr(fields[7])?;
+            Some(UnixRoute {
+                interface: fields[0].to_string(),
+                destination: IpAddr::V4(parse_addr(fields[1])?),
+                prefix: u32::from(mask).count_ones() as u8,
+                gateway: if flags & RTF_GATEWAY != 0 {
+                    Some(IpAddr::V4(gateway))
+                } else {
+                    None
+                },
+                metric: fields[6].parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Parse `/proc/net/ipv6_route`, addresses are 32 hex digits in network byte order.
+pub(crate) fn parse_ipv6_routes(data: &str) -> Vec<UnixRoute> {
+    let parse_addr = |value: &str| u128::from_str_radix(value, 16).ok().map(Ipv6Addr::from);
+    data.lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 10 {
+                return None;
+            }
+            let flags = u32::from_str_radix(fields[8], 16).ok()?;
+            if flags & RTF_UP == 0 || flags & RTF_REJECT != 0 || fields[9] == "lo" {
+                return None;
+            }
+            let gateway = parse_addr(fields[4])?;
+            Some(UnixRoute {
+                interface: fields[9].to_string(),
+                destination: IpAddr::V6(parse_addr(fields[0])?),
+                prefix: u8::from_str_radix(fields[1], 16).ok()?,
+                gateway: if gateway.is_unspecified() {
+                    None
+                } else {
+                    Some(IpAddr::V6(gateway))
+                },
+                metric: u32::from_str_radix(fields[5], 16).ok()?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sys::fixture;
+    use std::str::FromStr;
+
+    fn ip(value: &str) -> IpAddr {
+        IpAddr::from_str(value).unwrap()
+    }
+
+    #[test]
+    fn test_load() -> io::Result<()> {
+        let table = UnixRoutingTable::load(&fixture("basic"))?;
+        assert_eq!(table.routes.len(), 7);
+        assert_eq!(
+            table.gateways("eth0"),
+            vec![ip("192.168.113.1"), ip("fe80::22e:5fff:fe00:1")]
+        );
+        assert_eq!(table.gateways("eth1"), vec![ip("192.168.1.1")]);
+        assert_eq!(table.uplink(), Some("eth0".to_string()));
+        assert_eq!(table.default_gateway("eth1"), Some(ip("192.168.1.1")));
+        assert_eq!(table.default_gateway("lo"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_ipv4_routes() {
+        let routes = parse_ipv4_routes(
+            "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT\n\
+             eth1\t0001A8C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\t0\t0\t0\n",
+        );
+        assert_eq!(
+            routes,
+            vec![UnixRoute {
+                interface: "eth1".to_string(),
+                destination: ip("192.168.1.0"),
+                prefix: 24,
+                gateway: None,
+                metric: 0,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_default_route_metric() {
+        let table = UnixRoutingTable::new(parse_ipv4_routes(
+            "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT\n\
+             eth0\t00000000\t0171A8C0\t0003\t0\t0\t300\t00000000\t0\t0\t0\n\
+             eth2\t00000000\t0102000A\t0003\t0\t0\t50\t00000000\t0\t0\t0\n",
+        ));
+        let default_route = table.default_route().unwrap();
+        assert_eq!(default_route.interface, "eth2");
+        assert_eq!(default_route.gateway, Some(ip("10.0.2.1")));
+    }
+}
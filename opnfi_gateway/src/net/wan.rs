@@ -0,0 +1,468 @@
+use crate::net::device::{UnixNetworkDevice, UnixNetworkInterface};
+use crate::net::ports::{Port, PortRole};
+use crate::net::routes::UnixRoutingTable;
+use crate::sys::SysRoot;
+use lib_opnfi::inform::payload::net::{
+    OpnFiInformNetworkConfig, OpnFiInformNetworkConfigPPPoE, OpnFiInformNetworkConfigStatic,
+    OpnFiInformWanMode, OpnFiInformWanStatus,
+};
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::IpAddr;
+
+/// `ARPHRD_PPP` from `if_arp.h`, reported in `/sys/class/net/<if>/type`
+const ARPHRD_PPP: u32 = 512;
+
+// ===== WAN Health =====
+
+/// Health check result of a single WAN port.
+#[derive(PartialEq, Clone, Debug)]
+pub(crate) struct WanHealth {
+    pub port: Port,
+    pub gateway: Option<IpAddr>,
+    /// Link is up, a default route goes through it and the monitor, if any, reaches upstream
+    pub up: bool,
+    pub active: bool,
+}
+
+impl From<&WanHealth> for OpnFiInformWanStatus {
+    fn from(health: &WanHealth) -> Self {
+        OpnFiInformWanStatus {
+            name: health.port.name.clone(),
+            ifname: health.port.ifname.clone(),
+            gateway: health.gateway.map(|gw| gw.to_string()).unwrap_or_default(),
+            up: health.up,
+            active: health.active,
+        }
+    }
+}
+
+// ===== Failover =====
+
+/// Tracks which WANs are healthy and which one should carry traffic.
+/// The host decides failover and load balancing. A WAN the monitor finds unreachable is
+/// marked down, so `active` can name another WAN than the kernel's default route.
+#[derive(PartialEq, Clone, Debug)]
+pub(crate) struct WanFailover {
+    mode: OpnFiInformWanMode,
+    wans: Vec<WanHealth>,
+}
+
+impl WanFailover {
+    pub fn new(mode: OpnFiInformWanMode) -> Self {
+        WanFailover {
+            mode,
+            wans: Vec::new(),
+        }
+    }
+
+    pub fn mode(&self) -> OpnFiInformWanMode {
+        self.mode
+    }
+
+    /// Interface of the first active WAN
+    pub fn active(&self) -> Option<&str> {
+        self.wans
+            .iter()
+            .find(|w| w.active)
+            .map(|w| w.port.ifname.as_str())
+    }
+
+    /// Re-run the health checks, returns true when a WAN changed health or activity.
+    /// `reachability` holds the WAN monitor verdict per interface, unmonitored WANs are
+    /// judged by link and route alone.
+    pub fn update(
+        &mut self,
+        ports: &[Port],
+        devices: &[UnixNetworkDevice],
+        table: &UnixRoutingTable,
+        reachability: &BTreeMap<String, bool>,
+    ) -> bool {
+        let uplink = table.uplink();
+        let mut wans: Vec<WanHealth> = ports
+            .iter()
+            .filter(|port| port.role == PortRole::Wan)
+            .map(|port| {
+                let gateway = table.default_gateway(&port.ifname);
+                let link_up = devices
+                    .iter()
+                    .find(|d| d.name() == port.ifname)
+                    .map(|d| d.link().is_up())
+                    .unwrap_or(false);
+                let reachable = reachability.get(&port.ifname).copied().unwrap_or(true);
+                WanHealth {
+                    port: port.clone(),
+                    gateway,
+                    up: link_up && gateway.is_some() && reachable,
+                    active: false,
+                }
+            })
+            .collect();
+        match self.mode {
+            OpnFiInformWanMode::Failover => {
+                let active = wans
+                    .iter()
+                    .position(|w| w.up && Some(&w.port.ifname) == uplink.as_ref())
+                    .or_else(|| wans.iter().position(|w| w.up));
+                if let Some(active) = active {
+                    wans[active].active = true;
+                }
+            }
+            OpnFiInformWanMode::LoadBalance => {
+                for wan in wans.iter_mut() {
+                    wan.active = wan.up;
+                }
+            }
+        }
+        let changed = wans != self.wans;
+        self.wans = wans;
+        changed
+    }
+
+    pub fn status(&self) -> Vec<OpnFiInformWanStatus> {
+        self.wans.iter().map(|w| w.into()).collect()
+    }
+}
+
+// ===== Addressing =====
+
+/// Detect how a WAN gets its address.
+/// PPPoE links win, then the method in `/etc/network/interfaces`, then DHCP client leases,
+/// falling back to static when the interface has an IPv4 address.
+pub(crate) fn detect_wan_config(
+    root: &SysRoot,
+    interface: &UnixNetworkInterface,
+    table: &UnixRoutingTable,
+    nameservers: &[IpAddr],
+) -> OpnFiInformNetworkConfig {
+    let peers = pppd_peers(root);
+    if is_ppp(root, &interface.name) || carries_peer(&peers, &interface.name) {
+        return OpnFiInformNetworkConfig::PPPoE(OpnFiInformNetworkConfigPPPoE {
+            username: pppoe_username(&peers, &interface.name).unwrap_or_default(),
+        });
+    }
+    let method = interfaces_method(root, &interface.name);
+    let dhcp = match method.as_deref() {
+        Some("dhcp") => true,
+        Some(_) => false,
+        None => has_dhcp_lease(root, interface),
+    };
+    let address = interface.ips.iter().find(|ip| ip.ip.is_ipv4());
+    match address {
+        _ if dhcp => OpnFiInformNetworkConfig::DHCP,
+        Some(address) => {
+            let mut dns = nameservers.iter().map(|ns| ns.to_string());
+            OpnFiInformNetworkConfig::Static(OpnFiInformNetworkConfigStatic {
+                ip: address.ip.to_string(),
+                netmask: address.netmask().to_string(),
+                gateway: table
+                    .default_gateway(&interface.name)
+                    .map(|gw| gw.to_string())
+                    .unwrap_or_default(),
+                dns1: dns.next(),
+                dns2: dns.next(),
+            })
+        }
+        // No address, no lease and no configured method
+        None => OpnFiInformNetworkConfig::Disabled,
+    }
+}
+
+/// A pppd peer file, `/etc/ppp/peers/<name>`
+#[derive(PartialEq, Clone, Debug)]
+struct PppPeer {
+    /// Options split into words, quotes stripped
+    options: Vec<String>,
+}
+
+impl PppPeer {
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .position(|option| option == name)
+            .and_then(|index| self.options.get(index + 1))
+            .map(String::as_str)
+    }
+
+    /// The ppp interface pppd creates, set by `ifname` or `unit`
+    fn ppp_ifname(&self) -> Option<String> {
+        match (self.option("ifname"), self.option("unit")) {
+            (Some(ifname), _) => Some(ifname.to_string()),
+            (None, Some(unit)) => Some(format!("ppp{}", unit)),
+            (None, None) => None,
+        }
+    }
+}
+
+fn pppd_peers(root: &SysRoot) -> Vec<PppPeer> {
+    let peers = match fs::read_dir(root.path("/etc/ppp/peers")) {
+        Ok(peers) => peers,
+        Err(_) => return Vec::new(),
+    };
+    let mut paths: Vec<_> = peers.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+    paths.sort();
+    paths
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .map(|data| PppPeer {
+            options: data
+                .lines()
+                .map(|line| line.split('#').next().unwrap_or_default())
+                .flat_map(str::split_whitespace)
+                .map(|option| option.trim_matches('"').to_string())
+                .collect(),
+        })
+        .collect()
+}
+
+fn is_ppp(root: &SysRoot, ifname: &str) -> bool {
+    root.read_value::<u32, _>(format!("/sys/class/net/{}/type", ifname))
+        .map(|t| t == ARPHRD_PPP)
+        .unwrap_or_else(|_| ifname.starts_with("ppp"))
+}
+
+/// Whether a peer runs PPPoE over the interface
+fn carries_peer(peers: &[PppPeer], ifname: &str) -> bool {
+    let nic = format!("nic-{}", ifname);
+    peers
+        .iter()
+        .any(|peer| peer.options.iter().any(|o| *o == ifname || *o == nic))
+}
+
+/// PPPoE username of the peer behind a ppp link or carried by an interface.
+/// A ppp link without a matching `ifname` or `unit` only takes the peer when it is the only
+/// one that could have created it, None when that's ambiguous.
+fn pppoe_username(peers: &[PppPeer], ifname: &str) -> Option<String> {
+    let nic = format!("nic-{}", ifname);
+    let matching: Vec<&PppPeer> = peers
+        .iter()
+        .filter(|peer| {
+            peer.ppp_ifname().as_deref() == Some(ifname)
+                || peer.options.iter().any(|o| *o == ifname || *o == nic)
+        })
+        .collect();
+    let candidates = if matching.is_empty() {
+        peers
+            .iter()
+            .filter(|peer| peer.ppp_ifname().is_none())
+            .collect()
+    } else {
+        matching
+    };
+    match candidates.as_slice() {
+        [peer] => peer.option("user").map(String::from),
+        _ => None,
+    }
+}
+
+/// Address method, e.g. dhcp or static, from `/etc/network/interfaces`
+fn interfaces_method(root: &SysRoot, ifname: &str) -> Option<String> {
+    let data = root.read_to_string("/etc/network/interfaces").ok()?;
+    data.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            ["iface", name, "inet", method, ..] if *name == ifname => Some(method.to_string()),
+            _ => None,
+        }
+    })
+}
+
+/// Lease files left by dhclient, dhcpcd or systemd-networkd
+fn has_dhcp_lease(root: &SysRoot, interface: &UnixNetworkInterface) -> bool {
+    let name = &interface.name;
+    [
+        format!("/var/lib/dhcp/dhclient.{}.leases", name),
+        format!("/var/lib/dhclient/dhclient-{}.leases", name),
+        format!("/var/lib/dhcpcd/{}.lease", name),
+        format!("/var/lib/dhcpcd5/dhcpcd-{}.lease", name),
+        format!("/run/systemd/netif/leases/{}", interface.index),
+    ]
+    .iter()
+    .any(|path| root.path(path).exists())
+}
+
+/// IPv6 prefixes delegated to a WAN, from the latest dhclient DHCPv6 lease
+pub(crate) fn delegated_prefixes(root: &SysRoot, ifname: &str) -> Vec<String> {
+    let lease = [
+        format!("/var/lib/dhcp/dhclient6.{}.leases", ifname),
+        format!("/var/lib/dhclient/dhclient6-{}.leases", ifname),
+    ]
+    .iter()
+    .find_map(|path| root.read_to_string(path).ok());
+    let lease = match lease {
+        Some(lease) => lease,
+        None => return Vec::new(),
+    };
+    // dhclient appends leases, only the last one is current
+    let current = lease.rsplit("lease6").next().unwrap_or_default();
+    let mut prefixes = Vec::new();
+    for line in current.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if let ["iaprefix", prefix, ..] = fields.as_slice() {
+            if !prefixes.iter().any(|p| p == prefix) {
+                prefixes.push(prefix.to_string());
+            }
+        }
+    }
+    prefixes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::device::{fixture_interfaces, UnixNetworkAddress};
+    use crate::net::nameservers::get_nameservers;
+    use crate::net::routes::UnixRoute;
+    use crate::sys::fixture;
+    use std::io;
+    use std::net::Ipv4Addr;
+
+    fn default_route(interface: &str, gateway: [u8; 4], metric: u32) -> UnixRoute {
+        UnixRoute {
+            interface: interface.to_string(),
+            destination: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            prefix: 0,
+            gateway: Some(IpAddr::V4(Ipv4Addr::from(gateway))),
+            metric,
+        }
+    }
+
+    #[test]
+    fn test_failover() -> io::Result<()> {
+        let devices = UnixNetworkDevice::list_devices(&fixture("basic"), &fixture_interfaces())?;
+        let ports = vec![
+            Port::new("WAN", "eth0", PortRole::Wan),
+            Port::new("WAN2", "docker0", PortRole::Wan),
+            Port::new("LAN", "eth1", PortRole::Lan),
+        ];
+        let mut failover = WanFailover::new(OpnFiInformWanMode::Failover);
+
+        let table = UnixRoutingTable::new(vec![
+            default_route("eth0", [192, 168, 113, 1], 100),
+            default_route("docker0", [172, 17, 0, 1], 200),
+        ]);
+        assert!(failover.update(&ports, &devices, &table, &BTreeMap::new()));
+        assert_eq!(failover.wans.len(), 2);
+        assert_eq!(failover.active(), Some("eth0"));
+        assert!(!failover.update(&ports, &devices, &table, &BTreeMap::new()));
+
+        // Primary lost its default route
+        let table = UnixRoutingTable::new(vec![default_route("docker0", [172, 17, 0, 1], 200)]);
+        assert!(failover.update(&ports, &devices, &table, &BTreeMap::new()));
+        assert_eq!(failover.active(), Some("docker0"));
+        let status = failover.status();
+        assert!(!status[0].up);
+        assert!(status[1].active);
+        assert_eq!(status[1].gateway, "172.17.0.1");
+
+        // Primary is routed again but its upstream is dead
+        let table = UnixRoutingTable::new(vec![
+            default_route("eth0", [192, 168, 113, 1], 100),
+            default_route("docker0", [172, 17, 0, 1], 200),
+        ]);
+        let mut reachability = BTreeMap::new();
+        reachability.insert("eth0".to_string(), false);
+        failover.update(&ports, &devices, &table, &reachability);
+        assert_eq!(failover.active(), Some("docker0"));
+        assert!(!failover.status()[0].up);
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_balance() -> io::Result<()> {
+        let devices = UnixNetworkDevice::list_devices(&fixture("basic"), &fixture_interfaces())?;
+        let ports = vec![
+            Port::new("WAN", "eth0", PortRole::Wan),
+            Port::new("WAN2", "docker0", PortRole::Wan),
+            Port::new("WAN3", "eth1", PortRole::Wan),
+        ];
+        let mut failover = WanFailover::new(OpnFiInformWanMode::LoadBalance);
+        let table = UnixRoutingTable::new(vec![
+            default_route("eth0", [192, 168, 113, 1], 100),
+            default_route("docker0", [172, 17, 0, 1], 100),
+            default_route("eth1", [192, 168, 1, 1], 100),
+        ]);
+        failover.update(&ports, &devices, &table, &BTreeMap::new());
+        let active: Vec<bool> = failover.wans.iter().map(|w| w.active).collect();
+        // eth1 has a route but no carrier
+        assert_eq!(active, vec![true, true, false]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect_wan_config() -> io::Result<()> {
+        let root = fixture("basic");
+        let interfaces = fixture_interfaces();
+        let nameservers = get_nameservers(&root)?;
+        let table = UnixRoutingTable::load(&root)?;
+        let config = |interface: &UnixNetworkInterface| {
+            detect_wan_config(&root, interface, &table, &nameservers)
+        };
+        // eth0 has a dhclient lease, eth1 carries a pppd peer
+        assert_eq!(config(&interfaces[0]), OpnFiInformNetworkConfig::DHCP);
+        assert_eq!(
+            config(&interfaces[1]),
+            OpnFiInformNetworkConfig::PPPoE(OpnFiInformNetworkConfigPPPoE {
+                username: "opnfi@isp.example".to_string(),
+            })
+        );
+        // No address, lease or configured method
+        assert_eq!(config(&interfaces[2]), OpnFiInformNetworkConfig::Disabled);
+
+        let eth2 = UnixNetworkInterface {
+            name: "eth2".to_string(),
+            index: 5,
+            ips: vec![UnixNetworkAddress::new(
+                IpAddr::V4(Ipv4Addr::new(203, 0, 113, 2)),
+                29,
+            )],
+        };
+        let table = UnixRoutingTable::new(vec![default_route("eth2", [203, 0, 113, 1], 0)]);
+        assert_eq!(
+            detect_wan_config(&root, &eth2, &table, &nameservers),
+            OpnFiInformNetworkConfig::Static(OpnFiInformNetworkConfigStatic {
+                ip: "203.0.113.2".to_string(),
+                netmask: "255.255.255.248".to_string(),
+                gateway: "203.0.113.1".to_string(),
+                dns1: Some("1.1.1.1".to_string()),
+                dns2: Some("2606:4700:4700::1111".to_string()),
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_pppoe_username() {
+        let peer = |options: &str| PppPeer {
+            options: options.split_whitespace().map(String::from).collect(),
+        };
+        let dsl = peer("plugin rp-pppoe.so eth1 user dsl@isp.example");
+        let fiber = peer("plugin rp-pppoe.so nic-eth2 user fiber@isp.example unit 1");
+        let backup = peer("plugin rp-pppoe.so eth3 user backup@isp.example ifname ppp-backup");
+        let peers = vec![dsl.clone(), fiber, backup];
+
+        let user = |ifname: &str| pppoe_username(&peers, ifname);
+        assert_eq!(user("eth1"), Some("dsl@isp.example".to_string()));
+        assert_eq!(user("ppp1"), Some("fiber@isp.example".to_string()));
+        assert_eq!(user("ppp-backup"), Some("backup@isp.example".to_string()));
+        // Only the dsl peer could have created ppp0
+        assert_eq!(user("ppp0"), Some("dsl@isp.example".to_string()));
+        assert_eq!(
+            pppoe_username(&[dsl.clone(), dsl], "ppp0"),
+            None,
+            "Two peers without a unit are ambiguous"
+        );
+        assert!(carries_peer(&peers, "eth2"));
+        assert!(!carries_peer(&peers, "eth0"));
+    }
+
+    #[test]
+    fn test_delegated_prefixes() {
+        let root = fixture("basic");
+        assert_eq!(
+            delegated_prefixes(&root, "eth0"),
+            vec!["2001:db8:ff00::/56".to_string()]
+        );
+        assert!(delegated_prefixes(&root, "eth1").is_empty());
+    }
+}
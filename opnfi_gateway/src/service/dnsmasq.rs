@@ -0,0 +1,239 @@
+use crate::settings::DnsmasqSettings;
+use lib_opnfi::inform::payload::system_cfg::{OpnFiLanNetwork, OpnFiSystemConfig};
+use std::fmt::Write;
+use std::net::Ipv4Addr;
+use std::{fs, io, process};
+
+/// Keeps a dnsmasq config in sync with the networks provisioned by the controller.
+pub(crate) struct Dnsmasq {
+    settings: DnsmasqSettings,
+    /// The config was written but dnsmasq didn't reload it yet
+    reload_pending: bool,
+}
+
+impl Dnsmasq {
+    pub fn new(settings: DnsmasqSettings) -> Self {
+        Dnsmasq {
+            settings,
+            reload_pending: false,
+        }
+    }
+
+    /// Render the config and write it out, dnsmasq is only reloaded when the file changed
+    /// or the last reload failed.
+    /// Returns true if the config was changed.
+    pub fn apply(&mut self, system_cfg: &OpnFiSystemConfig) -> io::Result<bool> {
+        let rendered = render(system_cfg);
+        let path = self.settings.config_path.as_path();
+        let changed = match fs::read_to_string(path) {
+            Ok(current) => current != rendered,
+            Err(_) => true,
+        };
+        if changed {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(path, rendered)?;
+            self.reload_pending = true;
+        }
+        if self.reload_pending {
+            self.reload()?;
+            self.reload_pending = false;
+        }
+        Ok(changed)
+    }
+
+    fn reload(&self) -> io::Result<()> {
+        let mut args = self.settings.reload_command.iter();
+        let program = match args.next() {
+            Some(program) => program,
+            None => return Ok(()),
+        };
+        let status = process::Command::new(program).args(args).status()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!(
+                "dnsmasq reload exited with {}",
+                status
+            )))
+        }
+    }
+}
+
+/// Render a dnsmasq config for the DHCP and DNS settings in the system config.
+pub(crate) fn render(system_cfg: &OpnFiSystemConfig) -> String {
+    let mut out = String::new();
+    let forwarding = &system_cfg.service.dns.forwarding;
+    writeln!(
+        out,
+        "# Generated by opnfi_device, changes will be overwritten."
+    )
+    .unwrap();
+    if let Some(domain) = &system_cfg.system.domain_name {
+        writeln!(out, "domain={}", domain).unwrap();
+        writeln!(out, "local=/{}/", domain).unwrap();
+        writeln!(out, "expand-hosts").unwrap();
+    }
+    if let Some(cache_size) = &forwarding.cache_size {
+        writeln!(out, "cache-size={}", cache_size).unwrap();
+    }
+    for interface in &forwarding.except_interface {
+        writeln!(out, "except-interface={}", interface).unwrap();
+    }
+    if !forwarding.name_server.is_empty() {
+        writeln!(out, "no-resolv").unwrap();
+    }
+    for server in &forwarding.name_server {
+        writeln!(out, "server={}", server).unwrap();
+    }
+    for option in &forwarding.options {
+        writeln!(out, "{}", option).unwrap();
+    }
+    for network in system_cfg.lan_networks().iter().filter(|n| n.enabled) {
+        render_network(&mut out, network);
+    }
+    out
+}
+
+fn render_network(out: &mut String, network: &OpnFiLanNetwork) {
+    let tag = &network.name;
+    writeln!(out).unwrap();
+    writeln!(out, "# {} ({})", network.name, network.subnet).unwrap();
+    let netmask = subnet_netmask(&network.subnet);
+    let lease = match network.lease_time {
+        Some(seconds) => format!(",{}", seconds),
+        None => String::new(),
+    };
+    for (start, stop) in &network.ranges {
+        match netmask {
+            Some(netmask) => writeln!(
+                out,
+                "dhcp-range=set:{},{},{},{}{}",
+                tag, start, stop, netmask, lease
+            ),
+            None => writeln!(out, "dhcp-range=set:{},{},{}{}", tag, start, stop, lease),
+        }
+        .unwrap();
+    }
+    if let Some(router) = &network.router {
+        writeln!(out, "dhcp-option=tag:{},option:router,{}", tag, router).unwrap();
+    }
+    if !network.dns_servers.is_empty() {
+        writeln!(
+            out,
+            "dhcp-option=tag:{},option:dns-server,{}",
+            tag,
+            network.dns_servers.join(",")
+        )
+        .unwrap();
+    }
+    if let Some(domain) = &network.domain_name {
+        writeln!(out, "dhcp-option=tag:{},option:domain-name,{}", tag, domain).unwrap();
+    }
+    for lease in &network.static_leases {
+        writeln!(out, "dhcp-host={},{},{}", lease.mac, lease.ip, lease.name).unwrap();
+    }
+}
+
+/// Netmask for an IPv4 CIDR subnet, e.g. 192.168.1.0/24 -> 255.255.255.0
+fn subnet_netmask(subnet: &str) -> Option<Ipv4Addr> {
+    let prefix: u32 = subnet.split_once('/')?.1.parse().ok()?;
+    if prefix > 32 {
+        return None;
+    }
+    let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+    Some(Ipv4Addr::from(mask))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error;
+
+    type TestResult = std::result::Result<(), Box<dyn error::Error + 'static>>;
+
+    #[test]
+    fn test_render() -> TestResult {
+        let system_cfg = OpnFiSystemConfig::from_json(
+            r#"{
+            "service": {
+                "dhcp-server": {
+                    "shared-network-name": {
+                        "net_LAN": {
+                            "subnet": {
+                                "192.168.1.0/24": {
+                                    "default-router": "192.168.1.1",
+                                    "dns-server": ["192.168.1.1"],
+                                    "lease": "86400",
+                                    "start": { "192.168.1.6": { "stop": "192.168.1.254" } },
+                                    "static-mapping": {
+                                        "printer": {
+                                            "ip-address": "192.168.1.10",
+                                            "mac-address": "00:de:ad:be:ef:00"
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                },
+                "dns": { "forwarding": { "name-server": ["1.1.1.1"] } }
+            },
+            "system": { "domain-name": "localdomain" }
+        }"#,
+        )?;
+        let expected = "\
+# Generated by opnfi_device, changes will be overwritten.
+domain=localdomain
+local=/localdomain/
+expand-hosts
+no-resolv
+server=1.1.1.1
+
+# net_LAN (192.168.1.0/24)
+dhcp-range=set:net_LAN,192.168.1.6,192.168.1.254,255.255.255.0,86400
+dhcp-option=tag:net_LAN,option:router,192.168.1.1
+dhcp-option=tag:net_LAN,option:dns-server,192.168.1.1
+dhcp-option=tag:net_LAN,option:domain-name,localdomain
+dhcp-host=00:de:ad:be:ef:00,192.168.1.10,printer
+";
+        assert_eq!(render(&system_cfg), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_retries_reload() -> TestResult {
+        let dir = std::env::temp_dir().join(format!("opnfi-dnsmasq-{}", std::process::id()));
+        let marker = dir.join("reloaded");
+        let mut dnsmasq = Dnsmasq::new(DnsmasqSettings {
+            enabled: true,
+            config_path: dir.join("opnfi.conf"),
+            reload_command: vec!["false".to_string()],
+        });
+        let system_cfg = OpnFiSystemConfig::from_json(r#"{"system": {"domain-name": "lan"}}"#)?;
+        assert!(dnsmasq.apply(&system_cfg).is_err());
+
+        // The config is unchanged, but dnsmasq never loaded it
+        dnsmasq.settings.reload_command = vec!["touch".to_string(), marker.display().to_string()];
+        assert!(!dnsmasq.apply(&system_cfg)?);
+        assert!(marker.exists());
+
+        // Nothing left to reload
+        fs::remove_file(&marker)?;
+        assert!(!dnsmasq.apply(&system_cfg)?);
+        assert!(!marker.exists());
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_subnet_netmask() {
+        assert_eq!(
+            subnet_netmask("10.0.0.0/8"),
+            Some(Ipv4Addr::new(255, 0, 0, 0))
+        );
+        assert_eq!(subnet_netmask("0.0.0.0/0"), Some(Ipv4Addr::new(0, 0, 0, 0)));
+        assert_eq!(subnet_netmask("10.0.0.0"), None);
+    }
+}
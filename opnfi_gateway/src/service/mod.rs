@@ -0,0 +1,3 @@
+pub(crate) mod dnsmasq;
+pub(crate) mod monitor;
+pub(crate) mod speedtest;
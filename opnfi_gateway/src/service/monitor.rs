@@ -0,0 +1,464 @@
+use crate::net::device::UnixNetworkDevice;
+use crate::net::ports::{Port, PortRole};
+use crate::settings::{MonitorProbe, MonitorSettings, MonitorTarget};
+use lib_opnfi::inform::payload::net::OpnFiInformNetworkInterface;
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use std::collections::{BTreeMap, VecDeque};
+use std::ffi::CString;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{io, process, thread};
+
+/// Consecutive lost rounds after which a WAN's upstream counts as unreachable
+const UNREACHABLE_AFTER_LOST: usize = 3;
+
+// ===== Statistics =====
+
+/// Rolling probe results of a WAN, a round is lost when no target answered.
+#[derive(PartialEq, Clone, Debug)]
+pub(crate) struct WanMonitorStats {
+    window: usize,
+    samples: VecDeque<Option<Duration>>,
+    /// Rounds lost since the monitor started
+    drops: usize,
+}
+
+impl WanMonitorStats {
+    pub fn new(window: usize) -> Self {
+        WanMonitorStats {
+            window: window.max(1),
+            samples: VecDeque::new(),
+            drops: 0,
+        }
+    }
+
+    pub fn record(&mut self, sample: Option<Duration>) {
+        if sample.is_none() {
+            self.drops += 1;
+        }
+        self.samples.push_back(sample);
+        while self.samples.len() > self.window {
+            self.samples.pop_front();
+        }
+    }
+
+    fn answered(&self) -> Vec<Duration> {
+        self.samples.iter().filter_map(|sample| *sample).collect()
+    }
+
+    /// Average round trip of the answered rounds
+    pub fn latency(&self) -> Option<Duration> {
+        let answered = self.answered();
+        if answered.is_empty() {
+            return None;
+        }
+        Some(answered.iter().sum::<Duration>() / answered.len() as u32)
+    }
+
+    /// Average difference between consecutive answered rounds
+    pub fn jitter(&self) -> Option<Duration> {
+        let answered = self.answered();
+        if answered.len() < 2 {
+            return None;
+        }
+        let total: Duration = answered
+            .windows(2)
+            .map(|pair| match pair[1].checked_sub(pair[0]) {
+                Some(diff) => diff,
+                None => pair[0] - pair[1],
+            })
+            .sum();
+        Some(total / (answered.len() - 1) as u32)
+    }
+
+    /// Percentage of rounds lost in the window, None before the first round
+    pub fn loss(&self) -> Option<f64> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let lost = self
+            .samples
+            .iter()
+            .filter(|sample| sample.is_none())
+            .count();
+        Some(lost as f64 * 100.0 / self.samples.len() as f64)
+    }
+
+    pub fn availability(&self) -> Option<f64> {
+        self.loss().map(|loss| 100.0 - loss)
+    }
+
+    pub fn drops(&self) -> usize {
+        self.drops
+    }
+
+    /// False once the latest rounds were all lost
+    pub fn is_reachable(&self) -> bool {
+        let rounds = UNREACHABLE_AFTER_LOST.min(self.window);
+        self.samples.len() < rounds
+            || self
+                .samples
+                .iter()
+                .rev()
+                .take(rounds)
+                .any(|sample| sample.is_some())
+    }
+
+    /// Report latency, availability and drops on the WAN interface
+    pub fn apply(&self, interface: &mut OpnFiInformNetworkInterface) {
+        interface.latency = self
+            .latency()
+            .map(|latency| latency.as_millis() as usize)
+            .unwrap_or_default();
+        interface.availability = self.availability();
+        interface.drops = self.drops();
+    }
+}
+
+// ===== Monitor =====
+
+/// A WAN probes are sent from, they bind to its interface and TCP probes also to its address.
+#[derive(PartialEq, Clone, Debug)]
+pub(crate) struct WanProbeSource {
+    pub ifname: String,
+    pub address: Option<IpAddr>,
+}
+
+impl WanProbeSource {
+    /// Sources for the WAN ports, using the first IPv4 address of each
+    pub fn for_ports(ports: &[Port], devices: &[UnixNetworkDevice]) -> Vec<Self> {
+        ports
+            .iter()
+            .filter(|port| port.role == PortRole::Wan)
+            .map(|port| WanProbeSource {
+                ifname: port.ifname.clone(),
+                address: devices
+                    .iter()
+                    .find(|device| device.name() == port.ifname)
+                    .and_then(|device| {
+                        device
+                            .interface()
+                            .ips
+                            .iter()
+                            .find(|ip| ip.ip.is_ipv4())
+                            .map(|ip| ip.ip)
+                    }),
+            })
+            .collect()
+    }
+}
+
+/// Probes the monitor targets from every WAN at a fixed interval.
+/// Clones share the same sources and statistics.
+#[derive(Clone)]
+pub(crate) struct WanMonitor {
+    settings: MonitorSettings,
+    sources: Arc<Mutex<Vec<WanProbeSource>>>,
+    stats: Arc<Mutex<BTreeMap<String, WanMonitorStats>>>,
+}
+
+impl WanMonitor {
+    pub fn new(settings: MonitorSettings) -> Self {
+        WanMonitor {
+            settings,
+            sources: Arc::new(Mutex::new(Vec::new())),
+            stats: Arc::new(Mutex::new(BTreeMap::new())),
+        }
+    }
+
+    /// Replace the WANs to probe from, statistics of the WANs that remain are kept.
+    pub fn set_sources(&self, sources: Vec<WanProbeSource>) {
+        self.stats
+            .lock()
+            .unwrap()
+            .retain(|ifname, _| sources.iter().any(|source| &source.ifname == ifname));
+        *self.sources.lock().unwrap() = sources;
+    }
+
+    pub fn stats(&self, ifname: &str) -> Option<WanMonitorStats> {
+        self.stats.lock().unwrap().get(ifname).cloned()
+    }
+
+    /// Whether the upstream of each probed WAN answers, see [`WanMonitorStats::is_reachable`]
+    pub fn reachability(&self) -> BTreeMap<String, bool> {
+        self.stats
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(ifname, stats)| (ifname.clone(), stats.is_reachable()))
+            .collect()
+    }
+
+    /// Probe on a background thread for as long as the process runs
+    pub fn start(&self) {
+        let monitor = self.clone();
+        thread::spawn(move || loop {
+            let started = Instant::now();
+            monitor.probe();
+            let interval = Duration::from_secs(monitor.settings.interval.max(1));
+            if let Some(remaining) = interval.checked_sub(started.elapsed()) {
+                thread::sleep(remaining);
+            }
+        });
+    }
+
+    /// Run one round of probes from every WAN
+    pub fn probe(&self) {
+        let sources = self.sources.lock().unwrap().clone();
+        for source in sources.iter() {
+            let sample = self.probe_round(source);
+            let mut stats = self.stats.lock().unwrap();
+            let stats = stats
+                .entry(source.ifname.clone())
+                .or_insert_with(|| WanMonitorStats::new(self.settings.window));
+            stats.record(sample);
+            debug!(
+                "WAN monitor: {} latency {:?}, jitter {:?}, loss {:?}%",
+                source.ifname,
+                stats.latency(),
+                stats.jitter(),
+                stats.loss()
+            );
+        }
+    }
+
+    /// Average round trip of the targets that answered, None when none did
+    fn probe_round(&self, source: &WanProbeSource) -> Option<Duration> {
+        let timeout = Duration::from_secs(self.settings.timeout.max(1));
+        let answered: Vec<Duration> = self
+            .settings
+            .targets
+            .iter()
+            .filter_map(|target| match probe(source, target, timeout) {
+                Ok(rtt) => Some(rtt),
+                Err(e) => {
+                    debug!("WAN monitor: {} -> {}: {}", source.ifname, target.host, e);
+                    None
+                }
+            })
+            .collect();
+        if answered.is_empty() {
+            return None;
+        }
+        Some(answered.iter().sum::<Duration>() / answered.len() as u32)
+    }
+}
+
+// ===== Probes =====
+
+fn probe(
+    source: &WanProbeSource,
+    target: &MonitorTarget,
+    timeout: Duration,
+) -> io::Result<Duration> {
+    match target.probe {
+        MonitorProbe::Icmp => icmp_probe(&source.ifname, &target.host, timeout),
+        MonitorProbe::Tcp => tcp_probe(source, &target.host, target.port, timeout),
+    }
+}
+
+/// Round trip reported by the system `ping`, which has the privileges raw sockets need.
+fn icmp_probe(ifname: &str, host: &str, timeout: Duration) -> io::Result<Duration> {
+    let output = process::Command::new("ping")
+        .args(["-n", "-c", "1", "-W"])
+        .arg(timeout.as_secs().to_string())
+        .args(["-I", ifname, host])
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("No echo reply from {}", host),
+        ));
+    }
+    parse_ping_time(&String::from_utf8_lossy(&output.stdout)).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Missing round trip in ping output",
+        )
+    })
+}
+
+/// Round trip of a TCP handshake sent from `source`
+fn tcp_probe(
+    source: &WanProbeSource,
+    host: &str,
+    port: u16,
+    timeout: Duration,
+) -> io::Result<Duration> {
+    let addr = (host, port)
+        .to_socket_addrs()?
+        .find(|addr| match source.address {
+            Some(address) => address.is_ipv4() == addr.is_ipv4(),
+            None => true,
+        })
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Unable to resolve {}", host),
+            )
+        })?;
+    let socket = probe_socket(source, addr)?;
+    let started = Instant::now();
+    socket.connect_timeout(&SockAddr::from(addr), timeout)?;
+    Ok(started.elapsed())
+}
+
+/// TCP socket bound to the WAN interface like `ping -I`, so it leaves through that WAN
+/// whatever the routing table says, and to its address when it has one.
+fn probe_socket(source: &WanProbeSource, addr: SocketAddr) -> io::Result<Socket> {
+    let domain = if addr.is_ipv4() {
+        Domain::ipv4()
+    } else {
+        Domain::ipv6()
+    };
+    let socket = Socket::new(domain, Type::stream(), Some(Protocol::tcp()))?;
+    let ifname = CString::new(source.ifname.as_str())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    socket.bind_device(Some(&ifname))?;
+    if let Some(address) = source.address {
+        socket.bind(&SockAddr::from(SocketAddr::new(address, 0)))?;
+    }
+    Ok(socket)
+}
+
+/// Parse the `time=12.3 ms` of an echo reply
+fn parse_ping_time(output: &str) -> Option<Duration> {
+    let millis: f64 = output
+        .split_whitespace()
+        .find_map(|field| field.strip_prefix("time="))?
+        .parse()
+        .ok()?;
+    Some(Duration::from_secs_f64(millis / 1000.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::os::unix::io::AsRawFd;
+
+    fn ms(millis: u64) -> Option<Duration> {
+        Some(Duration::from_millis(millis))
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut stats = WanMonitorStats::new(4);
+        assert_eq!(stats.latency(), None);
+        assert_eq!(stats.availability(), None);
+        let mut interface = OpnFiInformNetworkInterface::default();
+        stats.apply(&mut interface);
+        assert_eq!(interface.availability, None);
+
+        for sample in [ms(100), ms(200), None, ms(150), ms(250)].iter() {
+            stats.record(*sample);
+        }
+        // The first sample is outside the window
+        assert_eq!(stats.latency(), ms(200));
+        assert_eq!(stats.jitter(), ms(75));
+        assert_eq!(stats.loss(), Some(25.0));
+        assert_eq!(stats.drops(), 1);
+        assert!(stats.is_reachable());
+
+        let mut interface = OpnFiInformNetworkInterface::default();
+        stats.apply(&mut interface);
+        assert_eq!(interface.latency, 200);
+        assert_eq!(interface.availability, Some(75.0));
+        assert_eq!(interface.drops, 1);
+    }
+
+    #[test]
+    fn test_reachable() {
+        let mut stats = WanMonitorStats::new(30);
+        stats.record(None);
+        stats.record(None);
+        // Too few rounds to tell
+        assert!(stats.is_reachable());
+        stats.record(None);
+        assert!(!stats.is_reachable());
+        stats.record(ms(20));
+        assert!(stats.is_reachable());
+
+        // A window shorter than the lost rounds needed
+        let mut stats = WanMonitorStats::new(1);
+        stats.record(None);
+        assert!(!stats.is_reachable());
+    }
+
+    #[test]
+    fn test_tcp_probe() -> io::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let open = listener.local_addr()?.port();
+        let closed = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+        let target = |port: u16| MonitorTarget {
+            probe: MonitorProbe::Tcp,
+            host: "127.0.0.1".to_string(),
+            port,
+        };
+        let monitor = |targets: Vec<MonitorTarget>| {
+            let monitor = WanMonitor::new(MonitorSettings {
+                targets,
+                ..MonitorSettings::default()
+            });
+            monitor.set_sources(vec![WanProbeSource {
+                ifname: "lo".to_string(),
+                address: Some("127.0.0.1".parse().unwrap()),
+            }]);
+            monitor.probe();
+            monitor.stats("lo").unwrap()
+        };
+
+        // One target answering keeps the round
+        let up = monitor(vec![target(closed), target(open)]);
+        assert!(up.latency().is_some());
+        assert_eq!(up.availability(), Some(100.0));
+
+        let down = monitor(vec![target(closed)]);
+        assert_eq!(down.latency(), None);
+        assert_eq!(down.availability(), Some(0.0));
+        assert_eq!(down.drops(), 1);
+        Ok(())
+    }
+
+    /// `SO_BINDTODEVICE` of a socket, socket2's getter never reports one
+    fn bound_device(socket: &Socket) -> io::Result<String> {
+        let mut name = [0u8; libc::IFNAMSIZ];
+        let mut len = name.len() as libc::socklen_t;
+        let result = unsafe {
+            libc::getsockopt(
+                socket.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_BINDTODEVICE,
+                name.as_mut_ptr().cast(),
+                &mut len,
+            )
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let name = &name[..len as usize];
+        Ok(String::from_utf8_lossy(name)
+            .trim_end_matches('\0')
+            .to_string())
+    }
+
+    #[test]
+    fn test_probe_socket() -> io::Result<()> {
+        let source = WanProbeSource {
+            ifname: "lo".to_string(),
+            address: None,
+        };
+        let socket = probe_socket(&source, "127.0.0.1:53".parse().unwrap())?;
+        assert_eq!(bound_device(&socket)?, "lo");
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_ping_time() {
+        let output = "PING 1.1.1.1 (1.1.1.1) from 192.168.113.10 eth0: 56(84) bytes of data.\n\
+                      64 bytes from 1.1.1.1: icmp_seq=1 ttl=57 time=12.5 ms\n";
+        assert_eq!(parse_ping_time(output), Some(Duration::from_micros(12500)));
+        assert_eq!(parse_ping_time("1 packets transmitted, 0 received"), None);
+    }
+}
@@ -0,0 +1,374 @@
+use crate::command::CmdHandler;
+use crate::settings::SpeedTestSettings;
+use crate::util::unix_time;
+use lib_opnfi::inform::payload::command::OpnFiInformCmd;
+use lib_opnfi::inform::payload::net::OpnFiInformNetworkInterface;
+use lib_opnfi::inform::payload::stats::OpnFiInformSpeedTestStatus;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{io, thread};
+
+// ===== State =====
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub(crate) enum SpeedTestPhase {
+    Idle,
+    Ping,
+    Download,
+    Upload,
+    Done,
+    Failed,
+}
+
+/// Progress and results of the last speed test.
+#[derive(PartialEq, Clone, Debug)]
+pub(crate) struct SpeedTestState {
+    pub phase: SpeedTestPhase,
+    /// Unix time the test was started
+    pub rundate: u64,
+    /// Seconds the test took
+    pub runtime: u64,
+    /// Latency in milliseconds
+    pub latency: usize,
+    /// Download throughput in Mbps
+    pub xput_download: f64,
+    /// Upload throughput in Mbps
+    pub xput_upload: f64,
+}
+
+impl Default for SpeedTestState {
+    fn default() -> Self {
+        SpeedTestState {
+            phase: SpeedTestPhase::Idle,
+            rundate: 0,
+            runtime: 0,
+            latency: 0,
+            xput_download: 0.0,
+            xput_upload: 0.0,
+        }
+    }
+}
+
+impl SpeedTestState {
+    pub fn is_running(&self) -> bool {
+        matches!(
+            self.phase,
+            SpeedTestPhase::Ping | SpeedTestPhase::Download | SpeedTestPhase::Upload
+        )
+    }
+
+    /// Status string used in the interface table
+    pub fn status_name(&self) -> &'static str {
+        match self.phase {
+            SpeedTestPhase::Idle => "Idle",
+            SpeedTestPhase::Ping | SpeedTestPhase::Download | SpeedTestPhase::Upload => "Running",
+            SpeedTestPhase::Done => "Success",
+            SpeedTestPhase::Failed => "Error",
+        }
+    }
+
+    /// Report the speed test on the WAN interface.
+    /// Measured throughput is in `speedtest-status`, the interface reports live throughput.
+    pub fn apply(&self, interface: &mut OpnFiInformNetworkInterface) {
+        interface.speedtest_status = self.status_name().to_string();
+        interface.speedtest_lastrun = self.rundate as usize;
+        interface.speedtest_ping = self.latency;
+    }
+}
+
+impl From<&SpeedTestState> for OpnFiInformSpeedTestStatus {
+    fn from(state: &SpeedTestState) -> Self {
+        let step = |phase: SpeedTestPhase, done: bool| {
+            if state.phase == phase {
+                OpnFiInformSpeedTestStatus::STATUS_RUNNING
+            } else if done {
+                OpnFiInformSpeedTestStatus::STATUS_DONE
+            } else {
+                OpnFiInformSpeedTestStatus::STATUS_IDLE
+            }
+        };
+        let (ping_done, download_done, upload_done) = match state.phase {
+            SpeedTestPhase::Download => (true, false, false),
+            SpeedTestPhase::Upload => (true, true, false),
+            SpeedTestPhase::Done => (true, true, true),
+            _ => (false, false, false),
+        };
+        OpnFiInformSpeedTestStatus {
+            latency: state.latency,
+            rundate: state.rundate as usize,
+            runtime: state.runtime as usize,
+            status_download: step(SpeedTestPhase::Download, download_done),
+            status_ping: step(SpeedTestPhase::Ping, ping_done),
+            status_summary: if state.is_running() {
+                OpnFiInformSpeedTestStatus::STATUS_RUNNING
+            } else if state.phase == SpeedTestPhase::Done {
+                OpnFiInformSpeedTestStatus::STATUS_DONE
+            } else if state.phase == SpeedTestPhase::Failed {
+                OpnFiInformSpeedTestStatus::STATUS_FAILED
+            } else {
+                OpnFiInformSpeedTestStatus::STATUS_IDLE
+            },
+            status_upload: step(SpeedTestPhase::Upload, upload_done),
+            xput_download: state.xput_download,
+            xput_upload: state.xput_upload,
+        }
+    }
+}
+
+// ===== Speed Test =====
+
+/// Measures WAN latency and throughput against a HTTP endpoint.
+/// Clones share the same state so one can be registered as a command handler.
+#[derive(Clone)]
+pub(crate) struct SpeedTest {
+    settings: SpeedTestSettings,
+    state: Arc<Mutex<SpeedTestState>>,
+}
+
+impl SpeedTest {
+    pub fn new(settings: SpeedTestSettings) -> Self {
+        SpeedTest {
+            settings,
+            state: Arc::new(Mutex::new(SpeedTestState::default())),
+        }
+    }
+
+    pub fn state(&self) -> SpeedTestState {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// Run the test on a background thread, returns false if one is already running.
+    pub fn start(&self) -> bool {
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.is_running() {
+                return false;
+            }
+            state.phase = SpeedTestPhase::Ping;
+        }
+        let speedtest = self.clone();
+        thread::spawn(move || {
+            if let Err(e) = speedtest.run() {
+                error!("Speed test: Err -> {}", e);
+            }
+        });
+        true
+    }
+
+    /// Run the test on the current thread.
+    pub fn run(&self) -> io::Result<()> {
+        self.update(|state| {
+            *state = SpeedTestState {
+                phase: SpeedTestPhase::Ping,
+                rundate: unix_time(),
+                ..SpeedTestState::default()
+            }
+        });
+        let started = Instant::now();
+        let result = self.measure();
+        let mut state = self.state.lock().unwrap();
+        state.runtime = started.elapsed().as_secs();
+        state.phase = match result {
+            Ok(_) => SpeedTestPhase::Done,
+            Err(_) => SpeedTestPhase::Failed,
+        };
+        result
+    }
+
+    fn measure(&self) -> io::Result<()> {
+        let timeout = Duration::from_secs(self.settings.timeout);
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(io::Error::other)?;
+
+        let latency = self.ping(timeout)?;
+        self.update(|state| {
+            state.latency = latency;
+            state.phase = SpeedTestPhase::Download;
+        });
+
+        let started = Instant::now();
+        let mut response = client
+            .get(self.settings.download_url.as_str())
+            .send()
+            .and_then(|r| r.error_for_status())
+            .map_err(io::Error::other)?;
+        let downloaded = response
+            .copy_to(&mut io::sink())
+            .map_err(io::Error::other)?;
+        let xput_download = mbps(downloaded as usize, started.elapsed());
+        self.update(|state| {
+            state.xput_download = xput_download;
+            state.phase = SpeedTestPhase::Upload;
+        });
+
+        let started = Instant::now();
+        client
+            .post(self.settings.upload_url.as_str())
+            .body(vec![0u8; self.settings.upload_size])
+            .send()
+            .and_then(|r| r.error_for_status())
+            .map_err(io::Error::other)?;
+        let xput_upload = mbps(self.settings.upload_size, started.elapsed());
+        self.update(|state| state.xput_upload = xput_upload);
+        Ok(())
+    }
+
+    /// Average TCP connect time to the download host in milliseconds
+    fn ping(&self, timeout: Duration) -> io::Result<usize> {
+        let url = reqwest::Url::parse(self.settings.download_url.as_str())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let host = url
+            .host_str()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Missing host"))?;
+        let port = url.port_or_known_default().unwrap_or(80);
+        let addr = (host, port).to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Unable to resolve {}", host),
+            )
+        })?;
+        let count = self.settings.ping_count.max(1);
+        let mut total = Duration::from_secs(0);
+        for _ in 0..count {
+            let started = Instant::now();
+            TcpStream::connect_timeout(&addr, timeout)?;
+            total += started.elapsed();
+        }
+        Ok((total.as_millis() / count as u128) as usize)
+    }
+
+    fn update<F: FnOnce(&mut SpeedTestState)>(&self, f: F) {
+        f(&mut self.state.lock().unwrap())
+    }
+}
+
+impl CmdHandler for SpeedTest {
+    fn handle(&mut self, cmd: &OpnFiInformCmd) -> io::Result<()> {
+        match cmd {
+            OpnFiInformCmd::SpeedTest => {
+                if !self.start() {
+                    warn!("Speed test is already running");
+                }
+                Ok(())
+            }
+            // Status is sent with every inform
+            OpnFiInformCmd::SpeedTestStatus => Ok(()),
+            cmd => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("SpeedTest can not handle {}", cmd.name()),
+            )),
+        }
+    }
+}
+
+/// Throughput in megabits per second
+fn mbps(bytes: usize, elapsed: Duration) -> f64 {
+    let seconds = elapsed.as_secs_f64().max(0.001);
+    (bytes as f64 * 8.0) / seconds / 1_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    /// Minimal HTTP server, GET returns `size` bytes and POST reads the body.
+    fn serve(size: usize) -> io::Result<String> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let mut rdr = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                if rdr.read_line(&mut request_line).unwrap_or(0) == 0 {
+                    continue;
+                }
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    rdr.read_line(&mut line).unwrap();
+                    let line = line.trim().to_lowercase();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some(value) = line.strip_prefix("content-length:") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0u8; content_length];
+                rdr.read_exact(&mut body).unwrap();
+                let response_body = if request_line.starts_with("GET") {
+                    vec![0u8; size]
+                } else {
+                    Vec::new()
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    response_body.len()
+                )
+                .unwrap();
+                stream.write_all(&response_body).unwrap();
+            }
+        });
+        Ok(format!("http://{}", addr))
+    }
+
+    #[test]
+    fn test_speedtest() -> io::Result<()> {
+        let url = serve(256 * 1024)?;
+        let speedtest = SpeedTest::new(SpeedTestSettings {
+            download_url: format!("{}/download", url),
+            upload_url: format!("{}/upload", url),
+            upload_size: 128 * 1024,
+            ping_count: 2,
+            timeout: 5,
+        });
+        speedtest.run()?;
+
+        let state = speedtest.state();
+        assert_eq!(state.phase, SpeedTestPhase::Done);
+        assert!(state.rundate > 0);
+        assert!(state.xput_download > 0.0);
+        assert!(state.xput_upload > 0.0);
+
+        let status = OpnFiInformSpeedTestStatus::from(&state);
+        assert_eq!(
+            status.status_summary,
+            OpnFiInformSpeedTestStatus::STATUS_DONE
+        );
+        assert_eq!(
+            status.status_upload,
+            OpnFiInformSpeedTestStatus::STATUS_DONE
+        );
+
+        let mut interface = OpnFiInformNetworkInterface::default();
+        state.apply(&mut interface);
+        assert_eq!(interface.speedtest_status, "Success");
+        Ok(())
+    }
+
+    #[test]
+    fn test_speedtest_failed() {
+        let speedtest = SpeedTest::new(SpeedTestSettings {
+            download_url: "not a url".to_string(),
+            ..SpeedTestSettings::default()
+        });
+        assert!(speedtest.run().is_err());
+        assert_eq!(speedtest.state().phase, SpeedTestPhase::Failed);
+        assert_eq!(speedtest.state().status_name(), "Error");
+        let status = OpnFiInformSpeedTestStatus::from(&speedtest.state());
+        assert_eq!(
+            status.status_summary,
+            OpnFiInformSpeedTestStatus::STATUS_FAILED
+        );
+    }
+}
@@ -0,0 +1,241 @@
+use lib_opnfi::inform::payload::net::OpnFiInformWanMode;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::{
+    fs,
+    io::{self, Read},
+    path,
+};
+
+/// Local device settings, unlike `Config` these are not managed by the controller
+/// and survive the device being forgotten.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+#[serde(default)]
+pub(crate) struct Settings {
+    pub clients: ClientSettings,
+    pub dpi: DpiSettings,
+    pub dnsmasq: DnsmasqSettings,
+    pub identity: IdentitySettings,
+    /// Emulated model code, e.g. UGW3 or UXGPRO
+    pub model: Option<String>,
+    pub monitor: MonitorSettings,
+    /// Logical port name, e.g. WAN, WAN2 or LAN1, to host interface.
+    /// Ports are auto-detected when empty.
+    pub ports: BTreeMap<String, String>,
+    pub reboot: RebootSettings,
+    pub speedtest: SpeedTestSettings,
+    pub wan: WanSettings,
+}
+
+impl Settings {
+    /// Load settings from a TOML file, a missing file gives the defaults.
+    pub fn load(path: &path::Path) -> io::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let mut file = fs::OpenOptions::new()
+            .write(false)
+            .read(true)
+            .create(false)
+            .open(path)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        toml::from_slice(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    }
+}
+
+// ===== Clients =====
+
+/// DHCP lease files clients are read from, missing files are skipped.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(default)]
+pub(crate) struct ClientSettings {
+    pub dnsmasq_leases: path::PathBuf,
+    pub dhcpd_leases: path::PathBuf,
+    pub kea_leases: path::PathBuf,
+}
+
+impl Default for ClientSettings {
+    fn default() -> Self {
+        ClientSettings {
+            dnsmasq_leases: path::PathBuf::from("/var/lib/misc/dnsmasq.leases"),
+            dhcpd_leases: path::PathBuf::from("/var/lib/dhcp/dhcpd.leases"),
+            kea_leases: path::PathBuf::from("/var/lib/kea/kea-leases4.csv"),
+        }
+    }
+}
+
+// ===== DPI =====
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+#[serde(default)]
+pub(crate) struct DpiSettings {
+    /// Off unless enabled, classifying conntrack needs `nf_conntrack_acct` for byte counts
+    pub enabled: bool,
+    /// nDPI JSON flow export used instead of classifying conntrack by port
+    pub import_path: Option<path::PathBuf>,
+}
+
+// ===== dnsmasq =====
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(default)]
+pub(crate) struct DnsmasqSettings {
+    pub enabled: bool,
+    pub config_path: path::PathBuf,
+    pub reload_command: Vec<String>,
+}
+
+impl Default for DnsmasqSettings {
+    fn default() -> Self {
+        DnsmasqSettings {
+            enabled: false,
+            config_path: path::PathBuf::from("/etc/dnsmasq.d/opnfi.conf"),
+            reload_command: vec![
+                "systemctl".to_string(),
+                "restart".to_string(),
+                "dnsmasq".to_string(),
+            ],
+        }
+    }
+}
+
+// ===== Identity =====
+
+/// Overrides for what the host reports about itself, detected when unset.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+#[serde(default)]
+pub(crate) struct IdentitySettings {
+    pub hostname: Option<String>,
+    pub board_vendor: Option<String>,
+    pub board_name: Option<String>,
+    pub bootrom_version: Option<String>,
+    /// Keeps the device's identity on the controller when the host is replaced
+    pub serial: Option<String>,
+    /// Firmware version reported instead of the model profile's required version
+    pub version: Option<String>,
+}
+
+// ===== WAN Monitor =====
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(default)]
+pub(crate) struct MonitorSettings {
+    /// Off unless enabled, probes reach out to the targets from every WAN
+    pub enabled: bool,
+    /// Seconds between probe rounds
+    pub interval: u64,
+    /// Timeout for each probe in seconds
+    pub timeout: u64,
+    /// Number of rounds latency and availability are computed over
+    pub window: usize,
+    /// A round is lost when none of the targets answer
+    pub targets: Vec<MonitorTarget>,
+}
+
+impl Default for MonitorSettings {
+    fn default() -> Self {
+        MonitorSettings {
+            enabled: false,
+            interval: 10,
+            timeout: 2,
+            window: 30,
+            targets: vec![
+                MonitorTarget {
+                    probe: MonitorProbe::Icmp,
+                    host: "1.1.1.1".to_string(),
+                    port: 0,
+                },
+                MonitorTarget {
+                    probe: MonitorProbe::Tcp,
+                    host: "8.8.8.8".to_string(),
+                    port: 53,
+                },
+            ],
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub(crate) struct MonitorTarget {
+    pub probe: MonitorProbe,
+    pub host: String,
+    /// Port connected to by TCP probes
+    #[serde(default)]
+    pub port: u16,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Copy, Clone, Debug)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum MonitorProbe {
+    /// Echo request sent with the system `ping`
+    Icmp,
+    /// Round trip of a TCP handshake
+    Tcp,
+}
+
+// ===== Reboot =====
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(default)]
+pub(crate) struct RebootSettings {
+    /// Log reboot requests instead of rebooting the host
+    pub dry_run: bool,
+    pub soft_command: Vec<String>,
+    pub hard_command: Vec<String>,
+}
+
+impl Default for RebootSettings {
+    fn default() -> Self {
+        RebootSettings {
+            dry_run: false,
+            soft_command: vec!["shutdown".to_string(), "-r".to_string(), "now".to_string()],
+            hard_command: vec!["reboot".to_string(), "-f".to_string()],
+        }
+    }
+}
+
+// ===== Speed Test =====
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(default)]
+pub(crate) struct SpeedTestSettings {
+    /// File downloaded to measure download throughput, its host is used for latency
+    pub download_url: String,
+    /// Endpoint accepting a POST to measure upload throughput
+    pub upload_url: String,
+    /// Bytes sent for the upload test
+    pub upload_size: usize,
+    /// Number of connections made to measure latency
+    pub ping_count: usize,
+    /// Timeout for each step in seconds
+    pub timeout: u64,
+}
+
+impl Default for SpeedTestSettings {
+    fn default() -> Self {
+        SpeedTestSettings {
+            download_url: "http://speedtest.tele2.net/10MB.zip".to_string(),
+            upload_url: "http://speedtest.tele2.net/upload.php".to_string(),
+            upload_size: 10 * 1024 * 1024,
+            ping_count: 4,
+            timeout: 30,
+        }
+    }
+}
+
+impl SpeedTestSettings {
+    /// Both endpoints are set, blank URLs turn the speed test off
+    pub fn is_configured(&self) -> bool {
+        !self.download_url.trim().is_empty() && !self.upload_url.trim().is_empty()
+    }
+}
+
+// ===== WAN =====
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+#[serde(default)]
+pub(crate) struct WanSettings {
+    /// How the host spreads traffic over multiple WANs, failover or load-balance
+    pub mode: OpnFiInformWanMode,
+}
@@ -0,0 +1,295 @@
+use crate::sys::SysRoot;
+use lib_opnfi::inform::payload::stats::{OpnFiInformSysStats, OpnFiInformSystemStatus};
+use std::collections::BTreeMap;
+use std::{fs, io};
+
+// ===== System Stats =====
+
+/// Collects system stats, CPU usage is measured between calls to `collect`.
+#[derive(PartialEq, Clone, Debug)]
+pub(crate) struct UnixSystemStats {
+    root: SysRoot,
+    cpu: UnixCpuSample,
+}
+
+impl UnixSystemStats {
+    /// The first `collect` reports the CPU usage since boot
+    pub fn new(root: &SysRoot) -> Self {
+        UnixSystemStats {
+            root: root.clone(),
+            cpu: UnixCpuSample::default(),
+        }
+    }
+
+    /// Sources that can't be read are left at zero or empty
+    pub fn collect(&mut self) -> OpnFiInformSystemStatus {
+        let mut status = OpnFiInformSystemStatus::default();
+
+        if let Ok(cpu) = UnixCpuSample::load(&self.root) {
+            status.cpu = round(cpu.total.usage_since(&self.cpu.total));
+            status.cpu_cores = cpu
+                .cores
+                .iter()
+                .enumerate()
+                .map(|(index, core)| {
+                    let previous = self.cpu.cores.get(index).copied().unwrap_or_default();
+                    round(core.usage_since(&previous))
+                })
+                .collect();
+            self.cpu = cpu;
+        }
+
+        if let Ok(meminfo) = self.root.read_to_string("/proc/meminfo") {
+            let memory = UnixMemoryInfo::parse(&meminfo);
+            if memory.total > 0 {
+                status.mem = round(memory.used() as f64 * 100.0 / memory.total as f64);
+            }
+        }
+
+        let sensors = UnixHwmonSensors::load(&self.root);
+        status.temps = sensors.temps;
+        status.fans = sensors.fans;
+        status
+    }
+
+    /// Load averages and memory in bytes, sources that can't be read are left at zero.
+    pub fn collect_sys(&self) -> OpnFiInformSysStats {
+        let mut stats = OpnFiInformSysStats::default();
+
+        if let Ok(loadavg) = self.root.read_to_string("/proc/loadavg") {
+            let mut loads = loadavg
+                .split_whitespace()
+                .map(|load| load.parse().unwrap_or_default());
+            stats.loadavg_1 = loads.next().unwrap_or_default();
+            stats.loadavg_5 = loads.next().unwrap_or_default();
+            stats.loadavg_15 = loads.next().unwrap_or_default();
+        }
+
+        if let Ok(meminfo) = self.root.read_to_string("/proc/meminfo") {
+            let memory = UnixMemoryInfo::parse(&meminfo);
+            stats.mem_total = memory.total;
+            stats.mem_used = memory.used();
+            stats.mem_buffer = memory.buffers + memory.cached;
+        }
+        stats
+    }
+}
+
+/// Percentages are reported with one decimal
+fn round(value: f64) -> f64 {
+    (value * 10.0).round() / 10.0
+}
+
+// ===== CPU =====
+
+/// Jiffies spent by a CPU, from a line of `/proc/stat`
+#[derive(PartialEq, Copy, Clone, Debug, Default)]
+pub(crate) struct UnixCpuTimes {
+    pub busy: u64,
+    pub total: u64,
+}
+
+impl UnixCpuTimes {
+    /// Parse the values after the `cpu` label, guest time is already part of user time.
+    fn parse(values: &[u64]) -> Self {
+        let total: u64 = values.iter().take(8).sum();
+        // idle and iowait
+        let idle: u64 = values.iter().skip(3).take(2).sum();
+        UnixCpuTimes {
+            busy: total - idle,
+            total,
+        }
+    }
+
+    /// Usage in percent since an earlier sample
+    pub fn usage_since(&self, previous: &UnixCpuTimes) -> f64 {
+        let total = self.total.saturating_sub(previous.total);
+        if total == 0 {
+            return 0.0;
+        }
+        self.busy.saturating_sub(previous.busy) as f64 * 100.0 / total as f64
+    }
+}
+
+/// Aggregate and per-core CPU times
+#[derive(PartialEq, Clone, Debug, Default)]
+pub(crate) struct UnixCpuSample {
+    pub total: UnixCpuTimes,
+    pub cores: Vec<UnixCpuTimes>,
+}
+
+impl UnixCpuSample {
+    pub fn load(root: &SysRoot) -> io::Result<Self> {
+        Ok(Self::parse(&root.read_to_string("/proc/stat")?))
+    }
+
+    pub fn parse(data: &str) -> Self {
+        let mut sample = UnixCpuSample::default();
+        for line in data.lines() {
+            let mut fields = line.split_whitespace();
+            let label = match fields.next() {
+                Some(label) if label.starts_with("cpu") => label,
+                _ => continue,
+            };
+            let values: Vec<u64> = fields.filter_map(|value| value.parse().ok()).collect();
+            let times = UnixCpuTimes::parse(&values);
+            if label == "cpu" {
+                sample.total = times;
+            } else {
+                sample.cores.push(times);
+            }
+        }
+        sample
+    }
+}
+
+// ===== Memory =====
+
+/// Values from `/proc/meminfo` in bytes
+#[derive(PartialEq, Copy, Clone, Debug, Default)]
+pub(crate) struct UnixMemoryInfo {
+    pub total: u64,
+    pub free: u64,
+    pub available: Option<u64>,
+    pub buffers: u64,
+    pub cached: u64,
+}
+
+impl UnixMemoryInfo {
+    pub fn parse(data: &str) -> Self {
+        let mut memory = UnixMemoryInfo::default();
+        for line in data.lines() {
+            let mut fields = line.split_whitespace();
+            let (key, value) = match (fields.next(), fields.next()) {
+                (Some(key), Some(value)) => (key, value.parse::<u64>().unwrap_or_default() * 1024),
+                _ => continue,
+            };
+            match key {
+                "MemTotal:" => memory.total = value,
+                "MemFree:" => memory.free = value,
+                "MemAvailable:" => memory.available = Some(value),
+                "Buffers:" => memory.buffers = value,
+                "Cached:" => memory.cached = value,
+                _ => {}
+            }
+        }
+        memory
+    }
+
+    /// Memory in use, kernels before 3.14 don't report `MemAvailable`
+    pub fn used(&self) -> u64 {
+        let available = self
+            .available
+            .unwrap_or(self.free + self.buffers + self.cached);
+        self.total.saturating_sub(available)
+    }
+}
+
+// ===== Sensors =====
+
+/// Temperatures and fan speeds from `/sys/class/hwmon`.
+/// Sensors without a label are named after their chip, e.g. `acpitz temp1`.
+#[derive(PartialEq, Clone, Debug, Default)]
+pub(crate) struct UnixHwmonSensors {
+    /// Degrees Celsius
+    pub temps: BTreeMap<String, f64>,
+    /// RPM
+    pub fans: BTreeMap<String, u64>,
+}
+
+impl UnixHwmonSensors {
+    pub fn load(root: &SysRoot) -> Self {
+        let mut sensors = UnixHwmonSensors::default();
+        let entries = match fs::read_dir(root.path("/sys/class/hwmon")) {
+            Ok(entries) => entries,
+            Err(_) => return sensors,
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let chip = fs::read_to_string(path.join("name"))
+                .map(|name| name.trim().to_string())
+                .unwrap_or_else(|_| entry.file_name().to_string_lossy().to_string());
+            let files = match fs::read_dir(&path) {
+                Ok(files) => files,
+                Err(_) => continue,
+            };
+            for file in files.flatten() {
+                let file_name = file.file_name().to_string_lossy().to_string();
+                let sensor = match file_name.strip_suffix("_input") {
+                    Some(sensor) => sensor,
+                    None => continue,
+                };
+                let value: f64 = match fs::read_to_string(file.path()) {
+                    Ok(value) => match value.trim().parse() {
+                        Ok(value) => value,
+                        Err(_) => continue,
+                    },
+                    Err(_) => continue,
+                };
+                let label = fs::read_to_string(path.join(format!("{}_label", sensor)))
+                    .map(|label| label.trim().to_string())
+                    .unwrap_or_else(|_| format!("{} {}", chip, sensor));
+                if sensor.starts_with("temp") {
+                    // Millidegrees
+                    sensors.temps.insert(label, value / 1000.0);
+                } else if sensor.starts_with("fan") {
+                    sensors.fans.insert(label, value as u64);
+                }
+            }
+        }
+        sensors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sys::fixture;
+
+    #[test]
+    fn test_collect() {
+        let mut stats = UnixSystemStats::new(&fixture("basic"));
+        let status = stats.collect();
+        // Usage since boot
+        assert_eq!(status.cpu, 26.4);
+        assert_eq!(status.cpu_cores, vec![28.0, 24.8]);
+        assert_eq!(status.mem, 30.1);
+        assert_eq!(status.temps["Package id 0"], 51.5);
+        assert_eq!(status.temps["coretemp temp2"], 49.0);
+        assert_eq!(status.temps["acpitz temp1"], 27.8);
+        assert_eq!(status.fans["CPU Fan"], 2100);
+        assert_eq!(status.fans["acpitz fan2"], 1200);
+
+        // Nothing ran since the previous sample
+        let status = stats.collect();
+        assert_eq!(status.cpu, 0.0);
+        assert_eq!(status.cpu_cores, vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_collect_sys() {
+        let stats = UnixSystemStats::new(&fixture("basic")).collect_sys();
+        assert_eq!(stats.loadavg_1, 0.08);
+        assert_eq!(stats.loadavg_15, 0.01);
+        assert_eq!(stats.mem_total, 4_030_512 * 1024);
+        assert_eq!(stats.mem_used, (4_030_512 - 2_815_780) * 1024);
+        assert_eq!(stats.mem_buffer, (184_320 + 1_843_200) * 1024);
+    }
+
+    #[test]
+    fn test_cpu_usage() {
+        let previous = UnixCpuSample::parse("cpu  100 0 100 800 0 0 0 0 0 0\n");
+        let current = UnixCpuSample::parse("cpu  250 0 150 1000 100 0 0 0 0 0\n");
+        // 200 busy out of 500
+        assert_eq!(current.total.usage_since(&previous.total), 40.0);
+        assert!(current.cores.is_empty());
+    }
+
+    #[test]
+    fn test_memory_without_available() {
+        let memory = UnixMemoryInfo::parse(
+            "MemTotal: 1000 kB\nMemFree: 200 kB\nBuffers: 100 kB\nCached: 300 kB\n",
+        );
+        assert_eq!(memory.used(), 400 * 1024);
+    }
+}
@@ -0,0 +1,82 @@
+use std::str::FromStr;
+use std::{fs, io, path};
+
+/// Root of the filesystem sysfs, procfs and /etc are read from.
+/// Tests point this at a fixture tree instead of the real system.
+#[derive(PartialEq, Clone, Debug)]
+pub(crate) struct SysRoot {
+    root: path::PathBuf,
+}
+
+impl SysRoot {
+    pub fn new<P: Into<path::PathBuf>>(root: P) -> Self {
+        SysRoot { root: root.into() }
+    }
+
+    /// Resolve an absolute system path, e.g. `/sys/class/net`, under the root.
+    pub fn path<P: AsRef<path::Path>>(&self, system_path: P) -> path::PathBuf {
+        let system_path = system_path.as_ref();
+        self.root
+            .join(system_path.strip_prefix("/").unwrap_or(system_path))
+    }
+
+    pub fn read_to_string<P: AsRef<path::Path>>(&self, system_path: P) -> io::Result<String> {
+        fs::read_to_string(self.path(system_path))
+    }
+
+    /// Read and parse a single trimmed value, as found in sysfs attributes.
+    pub fn read_value<T: FromStr, P: AsRef<path::Path>>(&self, system_path: P) -> io::Result<T>
+    where
+        T::Err: ToString,
+    {
+        let value = self.read_to_string(system_path)?;
+        T::from_str(value.trim())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+    }
+}
+
+impl Default for SysRoot {
+    fn default() -> Self {
+        SysRoot::new("/")
+    }
+}
+
+/// Root of the fixture trees used by tests
+#[cfg(test)]
+pub(crate) fn fixture(name: &str) -> SysRoot {
+    SysRoot::new(
+        path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("fixtures")
+            .join(name),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path() {
+        let root = SysRoot::new("/tmp/fixture");
+        assert_eq!(
+            root.path("/sys/class/net"),
+            path::PathBuf::from("/tmp/fixture/sys/class/net")
+        );
+        assert_eq!(
+            SysRoot::default().path("/etc/resolv.conf"),
+            path::PathBuf::from("/etc/resolv.conf")
+        );
+    }
+
+    #[test]
+    fn test_read_value() -> io::Result<()> {
+        let root = fixture("basic");
+        let mtu: usize = root.read_value("/sys/class/net/eth0/mtu")?;
+        assert_eq!(mtu, 1500);
+        assert!(root
+            .read_value::<usize, _>("/sys/class/net/eth0/operstate")
+            .is_err());
+        Ok(())
+    }
+}